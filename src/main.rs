@@ -18,8 +18,16 @@
 use clap::Parser;
 use eee_hyst::Time;
 
-use arq_simul::simulator::{Link, Network, Simulator, Terminal};
-use log::error;
+use arq_simul::simulator::{
+    AckMode, AckPolicy, Aqm, BitErrors, BitFlips, ChannelTrace, Checksum, CoDel, GilbertElliott,
+    Jitter, Link, LinkAddress, LossPattern, LossScript, LossTrace, Network, PacketErrors, Protocol,
+    Queue, QueueLimit, Red, RtoEvent, RtoTraceEntry, Scenario, Simulator, Terminal,
+    TerminalAddress,
+};
+use log::{error, warn};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process;
 
 /// A simple discrete time event simulator that shows the behavior of the main
 /// ARQ algorithms. It is built with didactic objectives to be used in
@@ -43,14 +51,192 @@ struct Args {
     #[clap(short = 'w', long = "wsize", default_value = "1")]
     tx_window: u16,
 
+    /// Send data in both directions
+    #[clap(long = "bidirectional")]
+    bidirectional: bool,
+
+    /// Piggyback ACKs in data packets, waiting up to this long for one, in seconds
+    #[clap(long = "piggyback", requires = "bidirectional")]
+    piggyback_wait: Option<f64>,
+
+    /// ARQ protocol
+    #[clap(long = "protocol", value_enum, default_value_t = Protocol::Hybrid)]
+    protocol: Protocol,
+
+    /// Sequence number field width, in bits [default: unbounded]
+    #[clap(long = "seq-bits", value_parser = clap::value_parser!(u32).range(0..64))]
+    seq_bits: Option<u32>,
+
+    /// ACK semantics [default: individual for sr, last otherwise]. When given, cumulative ACKs also answer duplicate and out-of-order packets
+    #[clap(long = "ack", value_enum)]
+    ack_mode: Option<AckMode>,
+
+    /// Acknowledge once every this many packets received in order [default: 1, unlimited with --ack-delay]
+    #[clap(long = "ack-every", value_parser = clap::value_parser!(u64).range(1..))]
+    ack_every: Option<u64>,
+
+    /// Delay pending ACKs up to this long, in seconds
+    #[clap(long = "ack-delay")]
+    ack_delay: Option<f64>,
+
+    /// Defer the ACKs of duplicate and out-of-order packets too
+    #[clap(long = "ack-defer-out-of-order")]
+    ack_defer_out_of_order: bool,
+
+    /// Send NAKs as soon as a missing packet is detected
+    #[clap(long = "nak")]
+    nak: bool,
+
+    /// Report up to this many blocks of packets received out of order in every ACK (requires --protocol sr)
+    #[clap(long = "sack", value_parser = clap::value_parser!(u8).range(1..=4))]
+    sack_blocks: Option<u8>,
+
+    /// Header bytes taken by each SACK block
+    #[clap(long = "sack-block-size", default_value = "8")]
+    sack_block_size: u32,
+
+    /// Estimate the retransmission timeout from RTT samples, starting from this value, in seconds
+    #[clap(long = "rto")]
+    initial_rto: Option<f64>,
+
+    /// Keep the adaptive retransmission timeout above this value, in seconds. RFC 6298 uses 1
+    #[clap(long = "min-rto", requires = "initial_rto")]
+    min_rto: Option<f64>,
+
+    /// Write the evolution of the adaptive retransmission timeout to this CSV file
+    #[clap(long = "rto-trace", requires = "initial_rto")]
+    rto_trace: Option<String>,
+
     /// Bit error rate
     #[clap(short = 'b', long = "ber", default_value = "0.0")]
     ber: f64,
 
+    /// Bit error rate during error bursts, turning --ber into the rate between them
+    #[clap(long = "burst-ber", requires_all = ["burst_length", "burst_interval"])]
+    burst_ber: Option<f64>,
+
+    /// Mean duration of the error bursts, in seconds
+    #[clap(long = "burst-length", requires = "burst_ber")]
+    burst_length: Option<f64>,
+
+    /// Mean time between error bursts, in seconds
+    #[clap(long = "burst-interval", requires = "burst_ber")]
+    burst_interval: Option<f64>,
+
+    /// Flip actual bits following the BER and let this code detect the errors
+    #[clap(long = "checksum", value_enum, conflicts_with_all = ["burst_ber", "per", "loss_pattern", "loss_trace", "checksum_miss"])]
+    checksum: Option<Checksum>,
+
+    /// Lose every packet with this probability, whatever its length, instead of using the BER
+    #[clap(long = "per", conflicts_with_all = ["burst_ber", "loss_pattern", "loss_trace"])]
+    per: Option<f64>,
+
+    /// Lose packets following this repeating pattern, e.g. 0001 loses one in four
+    #[clap(long = "loss-pattern", conflicts_with_all = ["burst_ber", "loss_trace"])]
+    loss_pattern: Option<String>,
+
+    /// Replay the losses in this file, with a line per packet holding 1 if it is lost or 0 otherwise
+    #[clap(long = "loss-trace", conflicts_with = "burst_ber")]
+    loss_trace: Option<String>,
+
+    /// Drop these packets, e.g. "kind=data n=3; kind=ack seqno=5", see LossScript for the syntax
+    #[clap(long = "drop", conflicts_with = "drop_script")]
+    drop: Option<String>,
+
+    /// Drop the packets chosen by the rules in this file, one per line
+    #[clap(long = "drop-script")]
+    drop_script: Option<String>,
+
+    /// Replay the losses and propagation delays in this CSV file, with time,lost,delay records
+    #[clap(long = "channel-trace", conflicts_with_all = ["ber", "burst_ber", "per", "loss_pattern", "loss_trace", "checksum"])]
+    channel_trace: Option<String>,
+
+    /// Deliver an extra copy of a packet with this probability
+    #[clap(long = "duplicate")]
+    duplication: Option<f64>,
+
+    /// Probability that the checksum misses the errors in a packet, which then arrives corrupted
+    #[clap(long = "checksum-miss")]
+    checksum_miss: Option<f64>,
+
+    /// Let only one end transmit at a time
+    #[clap(long = "half-duplex")]
+    half_duplex: bool,
+
+    /// Size of the transmission queue in each direction, in packets [default: unlimited]
+    #[clap(long = "queue", conflicts_with = "queue_bytes")]
+    queue_packets: Option<u64>,
+
+    /// Size of the transmission queue in each direction, in bytes [default: unlimited]
+    #[clap(long = "queue-bytes")]
+    queue_bytes: Option<u64>,
+
+    /// Drop packets early with RED: MIN,MAX[,MAX_PROB[,WEIGHT]], with thresholds in packets
+    #[clap(long = "red", value_parser = Red::parse, conflicts_with = "codel")]
+    red: Option<Red>,
+
+    /// Drop packets that waited too long with CoDel: TARGET,INTERVAL, in seconds
+    #[clap(long = "codel", value_parser = CoDel::parse)]
+    codel: Option<CoDel>,
+
     /// Propagation delay, in seconds
     #[clap(short = 'p', long = "prop_delay", default_value = "1e-3")]
     delay: f64,
 
+    /// Add a hop behind a relay with this CAPACITY,DELAY, in bits/s and seconds, and the rest of
+    /// the link options. Can be repeated.
+    #[clap(long = "hop", value_parser = parse_hop)]
+    hops: Vec<(f64, f64)>,
+
+    /// Run ARQ over every hop, with relays acknowledging and retransmitting, instead of only
+    /// between the end terminals. Relays buffer a window of packets and hold back the previous
+    /// hop when full
+    #[clap(
+        long = "hop-by-hop",
+        requires = "hops",
+        conflicts_with = "bidirectional"
+    )]
+    hop_by_hop: bool,
+
+    /// Simulate the network, traffic and stop conditions described in this TOML or JSON file,
+    /// ignoring the options about them
+    #[clap(long = "scenario")]
+    scenario: Option<String>,
+
+    /// Run both end-to-end and hop-by-hop ARQ over the same hops and compare them
+    #[clap(long = "compare-arq", requires = "hops", conflicts_with_all = ["bidirectional", "hop_by_hop"])]
+    compare_arq: bool,
+
+    /// Share the link among several flows behind a common queue, one per window size given,
+    /// e.g. 4,16,64
+    #[clap(long = "flows", value_delimiter = ',', value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["hops", "bidirectional"])]
+    flows: Vec<u16>,
+
+    /// Capacity of the links from every terminal to the shared one, in bits/s [default: same as --capacity]
+    #[clap(long = "access-capacity", requires = "flows")]
+    access_capacity: Option<f64>,
+
+    /// Random delay added to the propagation delay: uniform:MAX, normal:MEAN,STD_DEV,
+    /// exponential:MEAN or pareto:SCALE,SHAPE, in seconds, with SHAPE > 1. Never longer than 60 s
+    #[clap(long = "jitter", value_parser = Jitter::parse)]
+    jitter: Option<Jitter>,
+
+    /// Keep packets from overtaking those sent before them
+    #[clap(long = "preserve-order")]
+    preserve_order: bool,
+
+    /// Capacity of the reverse direction, in bits/s [default: same as --capacity]
+    #[clap(long = "reverse-capacity")]
+    reverse_capacity: Option<f64>,
+
+    /// Propagation delay of the reverse direction, in seconds [default: same as --prop_delay]
+    #[clap(long = "reverse-delay")]
+    reverse_delay: Option<f64>,
+
+    /// Bit error rate of the reverse direction [default: same error model as the forward one]
+    #[clap(long = "reverse-ber")]
+    reverse_ber: Option<f64>,
+
     /// Simulation duration, in seconds
     #[clap(short = 'l', long = "duration", default_value = "0.1")]
     duration: f64,
@@ -64,6 +250,551 @@ struct Args {
     verbose: u8,
 }
 
+fn parse_hop(hop: &str) -> Result<(f64, f64), String> {
+    match hop.split_once(',').map(|(capacity, delay)| {
+        (
+            capacity.trim().parse::<f64>(),
+            delay.trim().parse::<f64>(),
+        )
+    }) {
+        Some((Ok(capacity), Ok(delay))) if capacity > 0.0 && delay >= 0.0 => Ok((capacity, delay)),
+        _ => Err(format!(
+            "Expected CAPACITY,DELAY with a strictly positive capacity and a positive delay, found {}",
+            hop
+        )),
+    }
+}
+
+fn configure_terminal(terminal: Terminal, args: &Args) -> Terminal {
+    let terminal = terminal.with_protocol(args.protocol);
+
+    let terminal = match args.seq_bits {
+        Some(seq_bits) => terminal.with_seq_bits(seq_bits),
+        None => terminal,
+    };
+
+    let terminal = match args.ack_mode {
+        Some(ack_mode) => terminal.with_ack_mode(ack_mode),
+        None => terminal,
+    };
+
+    let ack_delay = args.ack_delay.map(Time::from_secs);
+    let terminal = terminal.with_ack_policy(AckPolicy {
+        every: args
+            .ack_every
+            .unwrap_or(if ack_delay.is_some() { u64::MAX } else { 1 }),
+        delay: ack_delay,
+        immediate_out_of_order: !args.ack_defer_out_of_order,
+    });
+
+    let terminal = match args.piggyback_wait {
+        Some(wait) => terminal.with_piggybacking(Time::from_secs(wait)),
+        None => terminal,
+    };
+
+    let terminal = if args.nak {
+        terminal.with_naks()
+    } else {
+        terminal
+    };
+
+    let terminal = match args.sack_blocks {
+        Some(sack_blocks) => terminal.with_sack(sack_blocks.into(), args.sack_block_size),
+        None => terminal,
+    };
+
+    let terminal = match args.min_rto {
+        Some(min_rto) => terminal.with_min_rto(Time::from_secs(min_rto)),
+        None => terminal,
+    };
+
+    match args.initial_rto {
+        Some(initial_rto) => terminal.with_adaptive_rto(Time::from_secs(initial_rto)),
+        None => terminal,
+    }
+}
+
+fn create_link(args: &Args, capacity: f64, delay: Time) -> Result<Link, String> {
+    let link = create_lossy_link(args, capacity, delay)?;
+
+    let link = if args.reverse_capacity.is_some() || args.reverse_delay.is_some() {
+        link.with_reverse(
+            args.reverse_capacity.unwrap_or(capacity),
+            args.reverse_delay.map_or(delay, Time::from_secs),
+        )
+    } else {
+        link
+    };
+
+    let link = match args.reverse_ber {
+        Some(ber) => match args.checksum {
+            Some(checksum) => link.with_reverse_error_model(BitFlips::create(ber, checksum)),
+            None => link.with_reverse_error_model(BitErrors::create(ber)),
+        },
+        None => link,
+    };
+
+    let link = match args.jitter {
+        Some(jitter) => link.with_jitter(jitter),
+        None => link,
+    };
+
+    let link = if args.preserve_order {
+        link.with_preserved_order()
+    } else {
+        link
+    };
+
+    let link = match args.duplication {
+        Some(duplication) => link.with_duplication(duplication),
+        None => link,
+    };
+
+    let link = match args.checksum_miss {
+        Some(miss) => link.with_checksum_miss(miss),
+        None => link,
+    };
+
+    let limit = match (args.queue_packets, args.queue_bytes) {
+        (Some(packets), _) => Some(QueueLimit::Packets(packets)),
+        (None, Some(bytes)) => Some(QueueLimit::Bytes(bytes)),
+        (None, None) => None,
+    };
+    let aqm = match (args.red, args.codel) {
+        (Some(red), _) => Aqm::Red(red),
+        (None, Some(codel)) => Aqm::CoDel(codel),
+        (None, None) => Aqm::DropTail,
+    };
+    let link = link.with_queue(Queue::create(limit, aqm));
+
+    let link = if args.half_duplex {
+        link.with_half_duplex()
+    } else {
+        link
+    };
+
+    let script = match (&args.drop, &args.drop_script) {
+        (Some(rules), _) => rules.clone(),
+        (None, Some(path)) => fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the loss script {}: {}", path, e))?,
+        (None, None) => return Ok(link),
+    };
+
+    Ok(link.with_loss_script(LossScript::parse(&script)?))
+}
+
+fn create_lossy_link(args: &Args, capacity: f64, delay: Time) -> Result<Link, String> {
+    if let (Some(burst_ber), Some(interval), Some(length)) =
+        (args.burst_ber, args.burst_interval, args.burst_length)
+    {
+        let channel = GilbertElliott::create(args.ber, burst_ber, interval, length);
+        return Ok(Link::create(capacity, delay, channel));
+    }
+
+    if let Some(per) = args.per {
+        return Ok(Link::create(capacity, delay, PacketErrors::create(per)));
+    }
+
+    if let Some(pattern) = &args.loss_pattern {
+        return match LossPattern::parse(pattern) {
+            Some(pattern) => Ok(Link::create(capacity, delay, pattern)),
+            None => Err(format!("Invalid loss pattern {}", pattern)),
+        };
+    }
+
+    if let Some(path) = &args.channel_trace {
+        let trace = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the channel trace {}: {}", path, e))?;
+        let trace = ChannelTrace::parse(&trace)
+            .map_err(|e| format!("Invalid channel trace {}: {}", path, e))?;
+        return Ok(Link::create(capacity, delay, trace.losses()).with_delay_trace(trace.delays()));
+    }
+
+    if let Some(path) = &args.loss_trace {
+        let trace = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the loss trace {}: {}", path, e))?;
+        return match LossTrace::parse(&trace) {
+            Some(trace) => Ok(Link::create(capacity, delay, trace)),
+            None => Err(format!("Invalid loss trace {}", path)),
+        };
+    }
+
+    if let Some(checksum) = args.checksum {
+        return Ok(Link::create(
+            capacity,
+            delay,
+            BitFlips::create(args.ber, checksum),
+        ));
+    }
+
+    Ok(Link::create(capacity, delay, BitErrors::create(args.ber)))
+}
+
+/// Builds the path from the sender to the receiver over every hop, with ARQ running over
+/// each of them on its own if `hop_by_hop`
+fn create_network(
+    args: &Args,
+    hop_by_hop: bool,
+) -> Result<(Network, TerminalAddress, TerminalAddress, Vec<LinkAddress>), String> {
+    let links = [(args.capacity, args.delay)]
+        .into_iter()
+        .chain(args.hops.iter().copied())
+        .map(|(capacity, delay)| create_link(args, capacity, Time::from_secs(delay)))
+        .collect::<Result<Vec<Link>, String>>()?;
+
+    let sender = configure_terminal(
+        Terminal::create(args.header_length, args.payload_length, args.tx_window),
+        args,
+    );
+    let receiver = configure_terminal(
+        Terminal::create(
+            args.header_length,
+            if args.bidirectional {
+                args.payload_length
+            } else {
+                0
+            },
+            args.tx_window,
+        ),
+        args,
+    );
+
+    let mut network = Network::default();
+    let (src_addr, dst_addr, link_addrs) = if hop_by_hop {
+        network.add_hop_by_hop_path(sender, receiver, links)
+    } else {
+        network.add_path(sender, receiver, links)
+    };
+
+    Ok((network, src_addr, dst_addr, link_addrs))
+}
+
+/// Simulates until `duration` or until the network is `done`, with every source starting at
+/// its own time. Returns when it stopped.
+fn run(
+    network: &mut Network,
+    sources: &[(TerminalAddress, Time)],
+    duration: Time,
+    seed: Option<u64>,
+    done: impl Fn(&Network) -> bool,
+) -> Time {
+    let mut simulator = match seed {
+        Some(seed) => Simulator::from_seed(seed),
+        None => Simulator::default(),
+    };
+    let mut clock = Time(0);
+
+    for &(source, start) in sources {
+        simulator.add_events(&network.start(source, start));
+    }
+
+    while clock < duration && !done(network) {
+        match simulator.pop() {
+            Some(event) => {
+                clock = event.due_time;
+                let evs = network.process_event(event, clock, &mut simulator.rng);
+                simulator.add_events(&evs);
+            }
+            None => {
+                error!("We have run out out events!");
+                break;
+            }
+        }
+    }
+
+    clock
+}
+
+/// Runs the simulation described in the scenario file at `path`, with `seed` taking precedence
+/// over its own
+fn run_scenario(path: &str, seed: Option<u64>) -> Result<(), String> {
+    let scenario = Scenario::load(path)?;
+    let (mut network, flows) = scenario
+        .build()
+        .map_err(|e| format!("Invalid scenario {}: {}", path, e))?;
+
+    let sources = flows
+        .iter()
+        .map(|flow| (flow.src_addr, flow.start))
+        .collect::<Vec<(TerminalAddress, Time)>>();
+    let end = run(
+        &mut network,
+        &sources,
+        scenario.duration(),
+        seed.or(scenario.seed()),
+        |network| {
+            scenario.delivered().is_some_and(|delivered| {
+                flows.iter().all(|flow| {
+                    network
+                        .get_ref_terminal_by_addr(flow.dst_addr)
+                        .get_delivered_packets()
+                        >= delivered
+                })
+            })
+        },
+    );
+    println!("Simulated {} s", end.as_secs());
+
+    for link in network.links() {
+        println!(
+            "Link {} -> {}:",
+            scenario.node_name(link.src_addr),
+            scenario.node_name(link.dst_addr)
+        );
+        link.show_stats();
+    }
+    for relay in network.relays() {
+        relay.show_stats();
+    }
+
+    for flow in &flows {
+        println!(
+            "Flow {} -> {}:",
+            scenario.node_name(flow.src_addr),
+            scenario.node_name(flow.dst_addr)
+        );
+        network.get_ref_terminal_by_addr(flow.src_addr).show_stats();
+        network.get_ref_terminal_by_addr(flow.dst_addr).show_stats();
+
+        let delivered = network
+            .get_ref_terminal_by_addr(flow.dst_addr)
+            .get_delivered_packets();
+        let payload = network
+            .get_ref_terminal_by_addr(flow.src_addr)
+            .get_payload_size();
+        print!("Delivered {} packets", delivered);
+        if end > flow.start {
+            print!(
+                ", {} bits/s of goodput",
+                8.0 * (delivered * u64::from(payload)) as f64
+                    / (end.as_secs() - flow.start.as_secs())
+            );
+        }
+        match mean_delay(&delivery_delays(&network, flow.src_addr, flow.dst_addr)) {
+            Some(delay) => println!(", {} s mean delay", delay.as_secs()),
+            None => println!(),
+        }
+    }
+
+    Ok(())
+}
+
+/// Time from the first transmission of every packet by `src_addr` until its delivery at
+/// `dst_addr`, sorted
+fn delivery_delays(
+    network: &Network,
+    src_addr: TerminalAddress,
+    dst_addr: TerminalAddress,
+) -> Vec<Time> {
+    // Packets keep their sequence numbers all along the path
+    let mut delays = network
+        .get_ref_terminal_by_addr(src_addr)
+        .get_departures()
+        .iter()
+        .zip(network.get_ref_terminal_by_addr(dst_addr).get_deliveries())
+        .map(|(departure, delivery)| Time(delivery.0 - departure.0))
+        .collect::<Vec<Time>>();
+    delays.sort();
+
+    delays
+}
+
+fn mean_delay(delays: &[Time]) -> Option<Time> {
+    match delays.len() {
+        0 => None,
+        n => Some(Time(
+            delays.iter().map(|delay| delay.0).sum::<u64>() / n as u64,
+        )),
+    }
+}
+
+/// Runs the same path with ARQ only at the end terminals and over every hop, and prints how
+/// each of them does side by side
+fn compare_arq(args: &Args, capacity: f64, duration: Time) -> Result<(), String> {
+    let mut rows = vec![
+        ("Delivered packets", Vec::new()),
+        ("Efficiency (%)", Vec::new()),
+        ("Mean delay (s)", Vec::new()),
+        ("99th percentile delay (s)", Vec::new()),
+        ("Bytes over all the links", Vec::new()),
+    ];
+
+    for hop_by_hop in [false, true] {
+        let (mut network, src_addr, dst_addr, link_addrs) = create_network(args, hop_by_hop)?;
+        run(
+            &mut network,
+            &[(src_addr, Time(0))],
+            duration,
+            args.seed,
+            |_| false,
+        );
+
+        let delivered = network
+            .get_ref_terminal_by_addr(dst_addr)
+            .get_delivered_packets();
+        let delays = delivery_delays(&network, src_addr, dst_addr);
+
+        let stats = [
+            delivered.to_string(),
+            (100.0
+                * 8.0
+                * (delivered * u64::from(args.header_length + args.payload_length)) as f64
+                / (capacity * duration.as_secs()))
+            .to_string(),
+            mean_delay(&delays).map_or("-".to_string(), |delay| delay.as_secs().to_string()),
+            match delays.len() {
+                0 => "-".to_string(),
+                n => delays[(n - 1) * 99 / 100].as_secs().to_string(),
+            },
+            link_addrs
+                .iter()
+                .map(|&link_addr| network.get_ref_link_by_addr(link_addr).transmitted_bytes())
+                .sum::<u64>()
+                .to_string(),
+        ];
+        for ((_, values), stat) in rows.iter_mut().zip(stats) {
+            values.push(stat);
+        }
+    }
+
+    println!("{:<28}{:>24}{:>24}", "", "End to end", "Hop by hop");
+    for (name, values) in rows {
+        println!("{:<28}{:>24}{:>24}", name, values[0], values[1]);
+    }
+
+    Ok(())
+}
+
+/// Runs a flow for every window size in --flows through the link, behind a shared queue, and
+/// prints how they share it
+fn share_link(args: &Args, duration: Time) -> Result<(), String> {
+    let flows = args
+        .flows
+        .iter()
+        .map(|&tx_window| {
+            (
+                configure_terminal(
+                    Terminal::create(args.header_length, args.payload_length, tx_window),
+                    args,
+                ),
+                configure_terminal(Terminal::create(args.header_length, 0, tx_window), args),
+            )
+        })
+        .collect();
+    let access = Link::create(
+        args.access_capacity.unwrap_or(args.capacity),
+        Time(0),
+        BitErrors::create(0.0),
+    );
+    let bottleneck = create_link(args, args.capacity, Time::from_secs(args.delay))?;
+
+    let mut network = Network::default();
+    let (ends, bottleneck_addr) = network.add_dumbbell(flows, access, bottleneck);
+    let sources = ends
+        .iter()
+        .map(|&(src_addr, _)| (src_addr, Time(0)))
+        .collect::<Vec<(TerminalAddress, Time)>>();
+    run(&mut network, &sources, duration, args.seed, |_| false);
+
+    network.get_ref_link_by_addr(bottleneck_addr).show_stats();
+    for relay in network.relays() {
+        relay.show_stats();
+    }
+
+    let mut goodputs = Vec::with_capacity(ends.len());
+    for (flow, (&(src_addr, dst_addr), tx_window)) in ends.iter().zip(&args.flows).enumerate() {
+        let goodput = 8.0
+            * (network
+                .get_ref_terminal_by_addr(dst_addr)
+                .get_delivered_packets()
+                * u64::from(args.payload_length)) as f64
+            / duration.as_secs();
+        print!(
+            "Flow {} (window {}): goodput {} bits/s, {} retransmissions",
+            flow + 1,
+            tx_window,
+            goodput,
+            network
+                .get_ref_terminal_by_addr(src_addr)
+                .get_retransmissions()
+        );
+        match mean_delay(&delivery_delays(&network, src_addr, dst_addr)) {
+            Some(delay) => println!(", {} s mean delay", delay.as_secs()),
+            None => println!(),
+        }
+        goodputs.push(goodput);
+    }
+
+    let total = goodputs.iter().sum::<f64>();
+    let squares = goodputs
+        .iter()
+        .map(|goodput| goodput * goodput)
+        .sum::<f64>();
+    println!(
+        "Aggregate goodput: {} bits/s ({}% of the link)",
+        total,
+        100.0 * total / args.capacity
+    );
+    if squares > 0.0 {
+        println!(
+            "Jain's fairness index: {}",
+            total * total / (goodputs.len() as f64 * squares)
+        );
+    }
+
+    Ok(())
+}
+
+fn write_rto_trace(path: &str, trace: &[RtoTraceEntry]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "time,seqno,event,rtt,srtt,rttvar,rto")?;
+    for entry in trace {
+        let (event, rtt) = match entry.event {
+            RtoEvent::Sample(rtt) => ("sample", rtt.as_secs().to_string()),
+            RtoEvent::Ambiguous => ("ambiguous", String::new()),
+            RtoEvent::Backoff => ("backoff", String::new()),
+        };
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            entry.time.as_secs(),
+            entry.seqno,
+            event,
+            rtt,
+            entry
+                .srtt
+                .map_or(String::new(), |srtt| srtt.as_secs().to_string()),
+            entry.rttvar.as_secs(),
+            entry.rto.as_secs()
+        )?;
+    }
+
+    out.flush()
+}
+
+/// Warns about everything that delays packets beyond what the fixed timeout accounts for
+fn warn_fixed_timeout(args: &Args) {
+    let ignored = [
+        (args.jitter.is_some(), "the jitter"),
+        (args.flows.len() > 1, "the time spent in the shared queue"),
+        (args.bidirectional, "the data sent the other way"),
+    ];
+
+    for (_, what) in ignored.iter().filter(|(ignored, _)| *ignored) {
+        warn!(
+            "The fixed timeout ignores {}, consider using --rto to avoid spurious retransmissions",
+            what
+        );
+    }
+}
+
+/// Reports a mistake in the input files or options whatever the verbose level, and quits
+fn fail(error: String) -> ! {
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -79,22 +810,127 @@ fn main() {
         .filter_level(filter_level)
         .init();
 
+    if let Some(path) = &args.scenario {
+        if let Err(e) = run_scenario(path, args.seed) {
+            fail(e);
+        }
+        return;
+    }
+
     if args.capacity <= 0.0 {
         error!("Capacity has to be strictly positive.");
         return;
     }
 
+    if args
+        .reverse_capacity
+        .is_some_and(|capacity| capacity <= 0.0)
+    {
+        error!("Reverse capacity has to be strictly positive.");
+        return;
+    }
+
+    if args.access_capacity.is_some_and(|capacity| capacity <= 0.0) {
+        error!("Access capacity has to be strictly positive.");
+        return;
+    }
+
     if args.ber < 0.0 || args.ber > 1.0 {
         error!("BER has to be between 0 and 1");
         return;
     }
 
-    let delay = if args.delay >= 0.0 {
-        Time::from_secs(args.delay)
-    } else {
+    if args
+        .reverse_ber
+        .is_some_and(|ber| !(0.0..=1.0).contains(&ber))
+    {
+        error!("Reverse BER has to be between 0 and 1");
+        return;
+    }
+
+    if args
+        .burst_ber
+        .is_some_and(|ber| !(0.0..=1.0).contains(&ber))
+    {
+        error!("Burst BER has to be between 0 and 1");
+        return;
+    }
+
+    if args.per.is_some_and(|per| !(0.0..=1.0).contains(&per)) {
+        error!("PER has to be between 0 and 1");
+        return;
+    }
+
+    if args
+        .duplication
+        .is_some_and(|duplication| !(0.0..=1.0).contains(&duplication))
+    {
+        error!("Duplication probability has to be between 0 and 1");
+        return;
+    }
+
+    if args
+        .checksum_miss
+        .is_some_and(|miss| !(0.0..=1.0).contains(&miss))
+    {
+        error!("Checksum miss probability has to be between 0 and 1");
+        return;
+    }
+
+    if args.burst_length.is_some_and(|length| length <= 0.0)
+        || args.burst_interval.is_some_and(|interval| interval <= 0.0)
+    {
+        error!("Burst length and interval have to be strictly positive.");
+        return;
+    }
+
+    if args.protocol.is_stop_and_wait()
+        && (args.tx_window != 1 || args.flows.iter().any(|&tx_window| tx_window != 1))
+    {
+        error!("Stop-and-Wait protocols require a window of one packet.");
+        return;
+    }
+
+    if args.sack_blocks.is_some() && !args.protocol.buffers_out_of_order() {
+        error!("SACK blocks require a receiver that buffers out-of-order packets (sr).");
+        return;
+    }
+
+    if args.ack_delay.is_some_and(|delay| delay <= 0.0) {
+        error!("ACK delay has to be strictly positive.");
+        return;
+    }
+
+    let terminal = configure_terminal(Terminal::default(), &args);
+    if terminal.defers_individual_acks() {
+        error!("Only cumulative ACKs can be deferred.");
+        return;
+    }
+
+    if args.piggyback_wait.is_some_and(|wait| wait < 0.0) {
+        error!("Piggyback wait has to be positive.");
+        return;
+    }
+
+    if args.initial_rto.is_some_and(|rto| rto <= 0.0) {
+        error!("Initial RTO has to be strictly positive.");
+        return;
+    }
+
+    if args.min_rto.is_some_and(|rto| rto < 0.0) {
+        error!("Minimum RTO has to be positive.");
+        return;
+    }
+
+    if args.delay < 0.0 {
         error!("Propagation delay has to be positive.");
         return;
-    };
+    }
+
+    if args.reverse_delay.is_some_and(|delay| delay < 0.0) {
+        error!("Reverse propagation delay has to be positive.");
+        return;
+    }
 
     let duration = if args.duration > 0.0 {
         Time::from_secs(args.duration)
@@ -103,51 +939,141 @@ fn main() {
         return;
     };
 
-    let mut network = Network::default();
-    let (src_addr, _dst_addr, link_addr) = network.add_link_and_terminals(
-        Terminal::create(args.header_length, args.payload_length, args.tx_window),
-        Terminal::create(args.header_length, 0, args.tx_window),
-        Link::create(args.capacity, delay, args.ber),
-    );
+    // The capacity of the slowest hop limits the whole path
+    let capacity = args
+        .hops
+        .iter()
+        .map(|&(capacity, _)| capacity)
+        .fold(args.capacity, f64::min);
+    let reverse_capacity = args.reverse_capacity.unwrap_or(capacity);
 
-    let mut simulator = match args.seed {
-        Some(seed) => Simulator::from_seed(seed),
-        None => Simulator::default(),
-    };
-    let mut clock = Time(0);
+    if let Some(max_window) = terminal.max_window() {
+        // The flows replace the window given on its own
+        let windows = if args.flows.is_empty() {
+            std::slice::from_ref(&args.tx_window)
+        } else {
+            &args.flows
+        };
+        for &tx_window in windows {
+            if u64::from(tx_window) > max_window {
+                println!(
+                    "Window size {} exceeds the maximum of {} for {:?} with these sequence numbers, expect failures",
+                    tx_window, max_window, args.protocol
+                );
+            }
+        }
+    }
 
-    simulator.add_events(&network.start(src_addr, clock));
+    if args.initial_rto.is_none() {
+        warn_fixed_timeout(&args);
+    }
 
-    while clock < duration {
-        match simulator.pop() {
-            Some(event) => {
-                clock = event.due_time;
-                let evs = network.process_event(event, clock, &mut simulator.rng);
-                simulator.add_events(&evs);
-            }
-            None => {
-                error!("We have run out out events!");
-                break;
-            }
+    if !args.flows.is_empty() {
+        if let Err(e) = share_link(&args, duration) {
+            fail(e);
         }
+        return;
     }
 
-    let link = network.get_ref_link_by_addr(link_addr);
+    if args.compare_arq {
+        if let Err(e) = compare_arq(&args, capacity, duration) {
+            fail(e);
+        }
+        return;
+    }
 
-    link.show_stats();
-    let acked_packets = network
-        .get_ref_terminal_by_addr(src_addr)
-        .get_transmitted_packets();
+    let (mut network, src_addr, dst_addr, link_addrs) = match create_network(&args, args.hop_by_hop)
+    {
+        Ok(network) => network,
+        Err(e) => fail(e),
+    };
+
+    let sources = if args.bidirectional {
+        vec![(src_addr, Time(0)), (dst_addr, Time(0))]
+    } else {
+        vec![(src_addr, Time(0))]
+    };
+    run(&mut network, &sources, duration, args.seed, |_| false);
+
+    for &link_addr in &link_addrs {
+        let link = network.get_ref_link_by_addr(link_addr);
+
+        if link_addrs.len() > 1 {
+            println!(
+                "Link {} ({} -> {}):",
+                link_addr, link.src_addr, link.dst_addr
+            );
+        }
+        link.show_stats();
+        if link.is_half_duplex() {
+            println!(
+                "Medium utilisation: {}% from {}, {}% from {}",
+                100.0 * link.busy_time(link.src_addr).as_secs() / duration.as_secs(),
+                link.src_addr,
+                100.0 * link.busy_time(link.dst_addr).as_secs() / duration.as_secs(),
+                link.dst_addr
+            );
+        }
+    }
+    for relay in network.relays() {
+        relay.show_stats();
+    }
+    network.get_ref_terminal_by_addr(src_addr).show_stats();
+    network.get_ref_terminal_by_addr(dst_addr).show_stats();
+    if let Some(path) = &args.rto_trace {
+        let trace = network.get_ref_terminal_by_addr(src_addr).get_rto_trace();
+        if let Err(e) = write_rto_trace(path, trace) {
+            error!("Could not write the RTO trace to {}: {}", path, e);
+        }
+    }
+    // The sender only hears from the first relay when running ARQ over every hop
+    let acked_packets = if args.hop_by_hop {
+        network
+            .get_ref_terminal_by_addr(dst_addr)
+            .get_delivered_packets()
+    } else {
+        network
+            .get_ref_terminal_by_addr(src_addr)
+            .get_transmitted_packets()
+    };
     println!(
         "Acknowledged {} bytes ({} of data)",
         acked_packets * u64::from(args.header_length + args.payload_length),
         acked_packets * u64::from(args.payload_length)
     );
+    let receiver = network.get_ref_terminal_by_addr(dst_addr);
+    let (ack_traffic, sack_overhead) = (receiver.get_ack_traffic(), receiver.get_sack_overhead());
+    // SACK blocks are charged as extra headers of the data they acknowledge
     println!(
-        "Efficiency: {}% ({}% considering headers)",
+        "Efficiency: {}% ({}% considering headers{})",
         100.0 * 8.0 * (acked_packets * u64::from(args.header_length + args.payload_length)) as f64
-            / (args.capacity * duration.as_secs()),
-        100.0 * 8.0 * (acked_packets * u64::from(args.payload_length)) as f64
-            / (args.capacity * duration.as_secs())
+            / (capacity * duration.as_secs()),
+        100.0
+            * 8.0
+            * (acked_packets * u64::from(args.payload_length)).saturating_sub(sack_overhead) as f64
+            / (capacity * duration.as_secs()),
+        if sack_overhead > 0 {
+            " and SACK blocks"
+        } else {
+            ""
+        }
     );
+    println!(
+        "ACK traffic: {} bytes ({}% of the reverse channel)",
+        ack_traffic,
+        100.0 * 8.0 * ack_traffic as f64 / (reverse_capacity * duration.as_secs())
+    );
+    if args.bidirectional {
+        for (from, to) in [(src_addr, dst_addr), (dst_addr, src_addr)] {
+            let acked_packets = network
+                .get_ref_terminal_by_addr(from)
+                .get_transmitted_packets();
+            println!(
+                "Goodput {} -> {}: {} bits/s",
+                from,
+                to,
+                8.0 * (acked_packets * u64::from(args.payload_length)) as f64 / duration.as_secs()
+            );
+        }
+    }
 }
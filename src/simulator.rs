@@ -19,19 +19,24 @@ mod network;
 
 use eee_hyst::Time;
 use network::packet::Packet;
-use network::{LinkAddress, TerminalAddress};
 use rand::SeedableRng;
 use rand_pcg::Pcg64Mcg;
 use std::cmp::Ordering;
 use std::collections::binary_heap::BinaryHeap;
 
-pub use self::EventKind::{Payload, Timeout};
-pub use network::{Link, Network, Terminal};
+pub use self::EventKind::{AckTimeout, Payload, Timeout};
+pub use network::{
+    AckMode, AckPolicy, Aqm, BitErrors, BitFlips, ChannelTrace, Checksum, CoDel, DelayTrace,
+    ErrorModel, ErrorModelClone, Flow, GilbertElliott, Jitter, Link, LinkAddress, LossPattern,
+    LossScript, LossTrace, Network, PacketErrors, Protocol, Queue, QueueLimit, Reception, Red,
+    Relay, RtoEvent, RtoTraceEntry, Scenario, Terminal, TerminalAddress,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventKind {
     Payload(Packet),
     Timeout(u64),
+    AckTimeout,
 }
 
 impl EventKind {
@@ -39,6 +44,7 @@ impl EventKind {
         match self {
             Payload(_) => 0,
             Timeout(_) => 1,
+            AckTimeout => 2,
         }
     }
 }
@@ -58,7 +64,7 @@ impl PartialOrd for EventKind {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Target {
     Link(LinkAddress),
-    Terminal(TerminalAddress),
+    Node(TerminalAddress), // Either a terminal or a relay
 }
 
 #[derive(Debug, Clone, Copy, Eq)]
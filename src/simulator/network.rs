@@ -18,21 +18,42 @@
 pub mod address;
 mod link;
 pub mod packet;
+mod relay;
+mod scenario;
 mod terminal;
 
 use super::{Event, Target};
+use crate::simulator::Payload;
 use eee_hyst::Time;
-pub use link::{AttachedLink, Link, LinkAddress};
-pub use terminal::{AttachedTerminal, Terminal, TerminalAddress};
+pub use link::{
+    Aqm, AttachedLink, BitErrors, BitFlips, ChannelTrace, Checksum, CoDel, DelayTrace, ErrorModel,
+    ErrorModelClone, GilbertElliott, Jitter, Link, LinkAddress, LossPattern, LossScript, LossTrace,
+    PacketErrors, Queue, QueueLimit, Reception, Red,
+};
+pub use relay::Relay;
+pub use scenario::{Flow, Scenario};
+pub use terminal::{
+    AckMode, AckPolicy, AttachedTerminal, Protocol, RtoEvent, RtoTraceEntry, Terminal,
+    TerminalAddress,
+};
 
 use rand::Rng;
 
+use std::collections::BTreeMap;
 use std::vec::Vec;
 
+#[derive(Clone, Debug)]
+enum Node {
+    Terminal(Box<AttachedTerminal>),
+    Relay(Relay),
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Network {
-    nodes: Vec<AttachedTerminal>,
+    nodes: Vec<Node>,
     edges: Vec<AttachedLink>,
+    handoffs: BTreeMap<TerminalAddress, TerminalAddress>, // Between both halves of a relay running ARQ
+    feeders: BTreeMap<TerminalAddress, TerminalAddress>, // Sending half of a relay and who feeds it
 }
 
 impl Network {
@@ -45,22 +66,30 @@ impl Network {
         assert_eq!(self.nodes.len(), usize::from(terminal.addr));
 
         let address = terminal.addr;
-        self.nodes.push(terminal);
+        self.nodes.push(Node::Terminal(Box::new(terminal)));
 
         address
     }
 
-    fn add_link(&mut self, link: AttachedLink) -> LinkAddress {
-        let (src_link_addr, dst_link_addr) = (
-            self.get_ref_terminal_by_addr(link.src_addr).link_addr,
-            self.get_ref_terminal_by_addr(link.dst_addr).link_addr,
-        );
+    fn add_relay(&mut self, relay: Relay) -> TerminalAddress {
+        assert_eq!(self.nodes.len(), usize::from(relay.addr));
+
+        let address = relay.addr;
+        self.nodes.push(Node::Relay(relay));
 
+        address
+    }
+
+    fn add_link(&mut self, link: AttachedLink) -> LinkAddress {
         let element_addr = LinkAddress::create(self.edges.len());
-        self.edges.push(link);
 
-        assert_eq!(src_link_addr, element_addr);
-        assert_eq!(dst_link_addr, element_addr);
+        // Relays have many links, but terminals must be attached to this one
+        for addr in [link.src_addr, link.dst_addr] {
+            if let Some(Node::Terminal(terminal)) = self.nodes.get(usize::from(addr)) {
+                assert_eq!(terminal.link_addr, element_addr);
+            }
+        }
+        self.edges.push(link);
 
         element_addr
     }
@@ -71,39 +100,204 @@ impl Network {
         dst: Terminal,
         link: Link,
     ) -> (TerminalAddress, TerminalAddress, LinkAddress) {
-        let addr_orig = TerminalAddress::create(self.nodes.len());
-        let addr_dst = TerminalAddress::create(usize::from(addr_orig) + 1);
-        let link_addr = LinkAddress::create(self.edges.len());
+        let (addr_orig, addr_dst, link_addrs) = self.add_path(orig, dst, vec![link]);
+
+        (addr_orig, addr_dst, link_addrs[0])
+    }
+
+    /// Connects both terminals through `links`, in order from `orig`, with a relay between
+    /// every two consecutive links
+    pub fn add_path(
+        &mut self,
+        orig: Terminal,
+        dst: Terminal,
+        links: Vec<Link>,
+    ) -> (TerminalAddress, TerminalAddress, Vec<LinkAddress>) {
+        assert!(!links.is_empty(), "A path needs at least one link");
+
+        let first_node = self.nodes.len();
+        let path = (0..=links.len())
+            .map(|hop| TerminalAddress::create(first_node + hop))
+            .collect::<Vec<TerminalAddress>>();
+        let link_addrs = (0..links.len())
+            .map(|hop| LinkAddress::create(self.edges.len() + hop))
+            .collect::<Vec<LinkAddress>>();
+        let (addr_orig, addr_dst) = (path[0], path[links.len()]);
 
-        let (attached_orig, attached_dst) = (
-            orig.attach_to_link(addr_orig, link_addr),
-            dst.attach_to_link(addr_dst, link_addr),
+        assert_eq!(
+            self.add_terminal(orig.attach_to_link(addr_orig, link_addrs[0], addr_dst)),
+            addr_orig
         );
+        for hop in 1..links.len() {
+            let mut relay = Relay::create(path[hop]);
+            relay.add_route(addr_orig, link_addrs[hop - 1]);
+            relay.add_route(addr_dst, link_addrs[hop]);
+            assert_eq!(self.add_relay(relay), path[hop]);
+        }
+        assert_eq!(
+            self.add_terminal(dst.attach_to_link(addr_dst, link_addrs[links.len() - 1], addr_orig)),
+            addr_dst
+        );
+
+        for (hop, link) in links.into_iter().enumerate() {
+            assert_eq!(
+                self.add_link(link.attach_terminals(path[hop], path[hop + 1])),
+                link_addrs[hop]
+            );
+        }
+
+        self.add_path_delays(&path, &link_addrs);
+
+        (addr_orig, addr_dst, link_addrs)
+    }
+
+    /// Makes the fixed timeouts at both ends of `path`, going through `link_addrs`, wait for
+    /// the round trip over all of it
+    fn add_path_delays(&mut self, path: &[TerminalAddress], link_addrs: &[LinkAddress]) {
+        let (addr_orig, addr_dst) = (path[0], path[path.len() - 1]);
+        let hops = link_addrs.len();
+        let Network { nodes, edges, .. } = self;
+
+        for (addr, crossings) in [(addr_orig, 1..hops), (addr_dst, 0..hops - 1)] {
+            if let Node::Terminal(terminal) = &mut nodes[usize::from(addr)] {
+                for hop in crossings {
+                    let hop_src = if addr == addr_orig {
+                        path[hop]
+                    } else {
+                        path[hop + 1]
+                    };
+                    terminal.add_hop(&edges[usize::from(link_addrs[hop])], hop_src);
+                }
+            }
+        }
+    }
+
+    /// Connects every pair of terminals in `flows` through a shared `bottleneck` between two
+    /// relays, each terminal reaching its relay over a copy of `access`. Returns the addresses
+    /// of the terminals in every pair and that of the bottleneck.
+    pub fn add_dumbbell(
+        &mut self,
+        flows: Vec<(Terminal, Terminal)>,
+        access: Link,
+        bottleneck: Link,
+    ) -> (Vec<(TerminalAddress, TerminalAddress)>, LinkAddress) {
+        let first_node = self.nodes.len();
+        let first_link = self.edges.len();
+        let node = |n: usize| TerminalAddress::create(first_node + n);
+        let link = |n: usize| LinkAddress::create(first_link + n);
+
+        // Both relays come first, then the terminals and access links of every flow in turn
+        let (left, right, bottleneck_addr) = (node(0), node(1), link(0));
+        let ends = (0..flows.len())
+            .map(|flow| (node(2 + 2 * flow), node(3 + 2 * flow)))
+            .collect::<Vec<(TerminalAddress, TerminalAddress)>>();
+        let accesses = (0..flows.len())
+            .map(|flow| (link(1 + 2 * flow), link(2 + 2 * flow)))
+            .collect::<Vec<(LinkAddress, LinkAddress)>>();
+
+        let (mut left_relay, mut right_relay) = (Relay::create(left), Relay::create(right));
+        for (&(addr_orig, addr_dst), &(access_orig, access_dst)) in ends.iter().zip(&accesses) {
+            left_relay.add_route(addr_orig, access_orig);
+            left_relay.add_route(addr_dst, bottleneck_addr);
+            right_relay.add_route(addr_orig, bottleneck_addr);
+            right_relay.add_route(addr_dst, access_dst);
+        }
+        assert_eq!(self.add_relay(left_relay), left);
+        assert_eq!(self.add_relay(right_relay), right);
+
+        for ((orig, dst), (&(addr_orig, addr_dst), &(access_orig, access_dst))) in
+            flows.into_iter().zip(ends.iter().zip(&accesses))
+        {
+            assert_eq!(
+                self.add_terminal(orig.attach_to_link(addr_orig, access_orig, addr_dst)),
+                addr_orig
+            );
+            assert_eq!(
+                self.add_terminal(dst.attach_to_link(addr_dst, access_dst, addr_orig)),
+                addr_dst
+            );
+        }
 
-        assert_eq!(self.add_terminal(attached_orig), addr_orig);
-        assert_eq!(self.add_terminal(attached_dst), addr_dst);
         assert_eq!(
-            self.add_link(link.attach_terminals(addr_orig, addr_dst)),
-            link_addr
+            self.add_link(bottleneck.attach_terminals(left, right)),
+            bottleneck_addr
         );
+        for (&(addr_orig, addr_dst), &(access_orig, access_dst)) in ends.iter().zip(&accesses) {
+            assert_eq!(
+                self.add_link(access.clone().attach_terminals(addr_orig, left)),
+                access_orig
+            );
+            assert_eq!(
+                self.add_link(access.clone().attach_terminals(right, addr_dst)),
+                access_dst
+            );
+            self.add_path_delays(
+                &[addr_orig, left, right, addr_dst],
+                &[access_orig, bottleneck_addr, access_dst],
+            );
+        }
+
+        (ends, bottleneck_addr)
+    }
+
+    /// Connects both terminals through `links`, like add_path(), but running ARQ over every
+    /// link on its own. Relays get a terminal configured as `dst` to receive from the previous
+    /// link and one configured as `orig` to send what they get over the next one. They keep no
+    /// more than a window of packets, holding back the previous link when full.
+    pub fn add_hop_by_hop_path(
+        &mut self,
+        orig: Terminal,
+        dst: Terminal,
+        links: Vec<Link>,
+    ) -> (TerminalAddress, TerminalAddress, Vec<LinkAddress>) {
+        assert!(!links.is_empty(), "A path needs at least one link");
+
+        let mut ends: Vec<(TerminalAddress, TerminalAddress)> = Vec::with_capacity(links.len());
+        let mut link_addrs = Vec::with_capacity(links.len());
+
+        for (hop, link) in links.into_iter().enumerate() {
+            let sender = if hop == 0 { orig } else { orig.with_fed_data() };
+            let (sender_addr, receiver_addr, link_addr) =
+                self.add_link_and_terminals(sender, dst, link);
 
-        (addr_orig, addr_dst, link_addr)
+            if let Some(&(feeder_addr, relay_addr)) = ends.last() {
+                self.handoffs.insert(relay_addr, sender_addr);
+                self.feeders.insert(sender_addr, feeder_addr);
+                let room = self.get_ref_terminal_by_addr(sender_addr).get_window();
+                self.get_mut_terminal_by_addr(feeder_addr).set_credit(room);
+            }
+            ends.push((sender_addr, receiver_addr));
+            link_addrs.push(link_addr);
+        }
+
+        (ends[0].0, ends[ends.len() - 1].1, link_addrs)
     }
 
     pub fn get_ref_terminal_by_addr(&self, addr: TerminalAddress) -> &AttachedTerminal {
-        if let Some(terminal) = self.nodes.get(usize::from(addr)) {
+        if let Some(Node::Terminal(terminal)) = self.nodes.get(usize::from(addr)) {
             return terminal;
         }
 
-        panic!("No node at address {}", addr);
+        panic!("No terminal at address {}", addr);
     }
 
     pub fn get_mut_terminal_by_addr(&mut self, addr: TerminalAddress) -> &mut AttachedTerminal {
-        if let Some(terminal) = self.nodes.get_mut(usize::from(addr)) {
+        if let Some(Node::Terminal(terminal)) = self.nodes.get_mut(usize::from(addr)) {
             return terminal;
         }
 
-        panic!("No node at address {}", addr);
+        panic!("No terminal at address {}", addr);
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = &AttachedLink> {
+        self.edges.iter()
+    }
+
+    pub fn relays(&self) -> impl Iterator<Item = &Relay> {
+        self.nodes.iter().filter_map(|node| match node {
+            Node::Relay(relay) => Some(relay),
+            Node::Terminal(_) => None,
+        })
     }
 
     pub fn get_ref_link_by_addr(&self, addr: LinkAddress) -> &AttachedLink {
@@ -122,14 +316,75 @@ impl Network {
         panic!("Could not find link at address {}", addr);
     }
 
+    /// Passes the data delivered in order to `from` on to `to`, for it to send
+    fn hand_over<R: Rng>(
+        &mut self,
+        from: TerminalAddress,
+        to: TerminalAddress,
+        now: Time,
+        rng: &mut R,
+    ) -> Vec<Event> {
+        let available = self.get_ref_terminal_by_addr(from).get_delivered_packets();
+        let Network { nodes, edges, .. } = self;
+
+        match &mut nodes[usize::from(to)] {
+            Node::Terminal(terminal) => {
+                let link = &mut edges[usize::from(terminal.link_addr)];
+                terminal.feed(available, now, link, rng)
+            }
+            Node::Relay(_) => panic!("No terminal at address {}", to),
+        }
+    }
+
+    /// Lets `to` send as much as fits in the relay whose sending half is `from`: a window past
+    /// the last packet acknowledged to it. The relay is assumed to tell at once.
+    fn make_room<R: Rng>(
+        &mut self,
+        from: TerminalAddress,
+        to: TerminalAddress,
+        now: Time,
+        rng: &mut R,
+    ) -> Vec<Event> {
+        let sender = self.get_ref_terminal_by_addr(from);
+        let credit = sender.get_transmitted_packets() + sender.get_window();
+        let Network { nodes, edges, .. } = self;
+
+        match &mut nodes[usize::from(to)] {
+            Node::Terminal(terminal) => {
+                let link = &mut edges[usize::from(terminal.link_addr)];
+                terminal.grant(credit, now, link, rng)
+            }
+            Node::Relay(_) => panic!("No terminal at address {}", to),
+        }
+    }
+
     pub fn process_event<R: Rng>(&mut self, event: Event, now: Time, rng: &mut R) -> Vec<Event> {
         match event.target {
-            Target::Terminal(terminal_addr) => {
-                let terminal = self.get_ref_terminal_by_addr(terminal_addr);
-                let link = self.get_ref_link_by_addr(terminal.link_addr).clone();
+            Target::Node(node_addr) => {
+                let Network { nodes, edges, .. } = self;
+
+                let mut res = match &mut nodes[usize::from(node_addr)] {
+                    Node::Terminal(terminal) => {
+                        let link = &mut edges[usize::from(terminal.link_addr)];
+                        terminal.process(event, now, link, rng)
+                    }
+                    Node::Relay(relay) => {
+                        let link_addr = match event.kind {
+                            Payload(packet) => relay.route(packet.dst_addr),
+                            _ => panic!("Relay {} got an event with no packet", node_addr),
+                        };
+                        relay.process(event, now, &mut edges[usize::from(link_addr)], rng)
+                    }
+                };
+
+                if let Some(&next_addr) = self.handoffs.get(&node_addr) {
+                    res.extend(self.hand_over(node_addr, next_addr, now, rng));
+                }
+                if let Some(&feeder_addr) = self.feeders.get(&node_addr) {
+                    res.extend(self.make_room(node_addr, feeder_addr, now, rng));
+                }
 
-                self.get_mut_terminal_by_addr(terminal_addr)
-                    .process(event, now, &link)
+                res
             }
             Target::Link(link_addr) => self
                 .get_mut_link_by_addr(link_addr)
@@ -15,46 +15,197 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+mod bit_flips;
+mod channel_trace;
 mod datacounter;
+mod error_model;
+mod gilbert_elliott;
+mod jitter;
+mod loss_script;
+mod queue;
 
 use super::address::Address;
-use super::packet::Packet;
+use super::packet::{Packet, PacketKind};
 use super::Event;
 use super::TerminalAddress;
 use crate::simulator::{Payload, Target};
 use datacounter::DataCounter;
-use log::trace;
-use rand::Rng;
-use std::convert::TryFrom;
+
+pub use bit_flips::{BitFlips, Checksum};
+pub use channel_trace::{ChannelTrace, DelayTrace};
+
+pub use error_model::{
+    BitErrors, ErrorModel, ErrorModelClone, LossPattern, LossTrace, PacketErrors, Reception,
+};
+pub use gilbert_elliott::GilbertElliott;
+pub use jitter::Jitter;
+use log::{debug, trace};
+pub use loss_script::LossScript;
+pub use queue::{Aqm, CoDel, Queue, QueueLimit, Red};
+use rand::{Rng, RngCore};
 
 use eee_hyst::Time;
 
 pub type LinkAddress = Address;
 
-#[derive(Clone, Copy, Debug)]
-pub struct Link {
+/// What packets find when travelling in one direction
+#[derive(Clone, Debug)]
+struct Channel {
     capacity: f64,
     propagation_delay: Time,
-    bit_error_rate: f64,
+    error_model: Box<dyn ErrorModel>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Link {
+    forward: Channel,
+    reverse: Option<Channel>, // Same as forward if missing
+    loss_script: Option<LossScript>,
+    delay_trace: Option<DelayTrace>,
+    jitter: Option<Jitter>,
+    preserve_order: bool,
+    duplication: Option<f64>,
+    checksum_miss: Option<f64>,
+    queue: Queue,
+    half_duplex: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct AttachedLink {
     pub src_addr: TerminalAddress,
     pub dst_addr: TerminalAddress,
-    capacity: f64,
-    propagation_delay: Time,
-    bit_error_rate: f64,
+    forward: Channel,         // From src_addr to dst_addr
+    reverse: Option<Channel>, // From dst_addr to src_addr, if it differs
+    loss_script: Option<LossScript>,
+    delay_trace: Option<DelayTrace>,
+    jitter: Option<Jitter>,
+    preserve_order: bool,
+    duplication: Option<f64>,
+    checksum_miss: Option<f64>,
+    queues: [Queue; 2], // In each direction
+    half_duplex: bool,
+    busy_until: [Time; 2], // Only the first one when half duplex
+    busy_time: [Time; 2],
 
+    last_arrival: [Time; 2], // In each direction
+    reordered: u64,
+    duplicated: u64,
+    undetected: u64,
     counter: DataCounter,
 }
 
 impl Link {
-    pub fn create(capacity: f64, propagation_delay: Time, bit_error_rate: f64) -> Link {
+    pub fn create<E: ErrorModel + 'static>(
+        capacity: f64,
+        propagation_delay: Time,
+        error_model: E,
+    ) -> Link {
+        Link {
+            forward: Channel {
+                capacity,
+                propagation_delay,
+                error_model: Box::new(error_model),
+            },
+            reverse: None,
+            loss_script: None,
+            delay_trace: None,
+            jitter: None,
+            preserve_order: false,
+            duplication: None,
+            checksum_miss: None,
+            queue: Queue::default(),
+            half_duplex: false,
+        }
+    }
+
+    fn reverse(&self) -> Channel {
+        self.reverse.clone().unwrap_or_else(|| self.forward.clone())
+    }
+
+    /// Gives packets going back to the first attached terminal their own capacity and
+    /// propagation delay. From then on, each direction has its own copy of the error model.
+    pub fn with_reverse(self, capacity: f64, propagation_delay: Time) -> Link {
+        Link {
+            reverse: Some(Channel {
+                capacity,
+                propagation_delay,
+                ..self.reverse()
+            }),
+            ..self
+        }
+    }
+
+    /// Uses `error_model` for the packets going back to the first attached terminal
+    pub fn with_reverse_error_model<E: ErrorModel + 'static>(self, error_model: E) -> Link {
+        Link {
+            reverse: Some(Channel {
+                error_model: Box::new(error_model),
+                ..self.reverse()
+            }),
+            ..self
+        }
+    }
+
+    /// Drops the packets chosen by `loss_script` on top of those lost to the error model
+    pub fn with_loss_script(self, loss_script: LossScript) -> Link {
+        Link {
+            loss_script: Some(loss_script),
+            ..self
+        }
+    }
+
+    /// Takes the propagation delay of every packet from `delay_trace`, see
+    /// ChannelTrace::delays()
+    pub fn with_delay_trace(self, delay_trace: DelayTrace) -> Link {
+        Link {
+            delay_trace: Some(delay_trace),
+            ..self
+        }
+    }
+
+    /// Adds a random delay to the propagation delay of every packet
+    pub fn with_jitter(self, jitter: Jitter) -> Link {
+        Link {
+            jitter: Some(jitter),
+            ..self
+        }
+    }
+
+    /// Holds back packets that would otherwise overtake those sent before in the same direction
+    pub fn with_preserved_order(self) -> Link {
+        Link {
+            preserve_order: true,
+            ..self
+        }
+    }
+
+    /// Delivers a second copy of a packet with probability `duplication`
+    pub fn with_duplication(self, duplication: f64) -> Link {
         Link {
-            capacity,
-            propagation_delay,
-            bit_error_rate,
+            duplication: Some(duplication),
+            ..self
+        }
+    }
+
+    /// Lets packets hit by the error model go through, unnoticed, with probability
+    /// `checksum_miss`. Packets dropped by the loss script never arrive.
+    pub fn with_checksum_miss(self, checksum_miss: f64) -> Link {
+        Link {
+            checksum_miss: Some(checksum_miss),
+            ..self
+        }
+    }
+
+    /// Puts a copy of `queue` in front of each direction
+    pub fn with_queue(self, queue: Queue) -> Link {
+        Link { queue, ..self }
+    }
+
+    /// Lets only one end transmit at a time
+    pub fn with_half_duplex(self) -> Link {
+        Link {
+            half_duplex: true,
+            ..self
         }
     }
 
@@ -66,39 +217,189 @@ impl Link {
         AttachedLink {
             src_addr,
             dst_addr,
-            capacity: self.capacity,
-            propagation_delay: self.propagation_delay,
-            bit_error_rate: self.bit_error_rate,
+            forward: self.forward.clone(),
+            reverse: self.reverse.clone(),
+            loss_script: self.loss_script.clone(),
+            delay_trace: self.delay_trace.clone(),
+            jitter: self.jitter,
+            preserve_order: self.preserve_order,
+            duplication: self.duplication,
+            checksum_miss: self.checksum_miss,
+            queues: [self.queue.clone(), self.queue.clone()],
+            half_duplex: self.half_duplex,
+            busy_until: [Time(0); 2],
+            busy_time: [Time(0); 2],
 
+            last_arrival: [Time(0); 2],
+            reordered: 0,
+            duplicated: 0,
+            undetected: 0,
             counter: DataCounter::default(),
         }
     }
 }
 
 impl AttachedLink {
-    fn drop_packet<R: Rng>(&self, packet: Packet, rng: &mut R) -> bool {
-        let bit_size = i32::try_from(8 * (packet.header_size + packet.payload_size)).unwrap();
-        let prob_tx = (1.0 - self.bit_error_rate).powi(bit_size);
+    fn direction(&self, packet: Packet) -> usize {
+        usize::from(packet.hop_src == self.dst_addr)
+    }
+
+    /// The node attached to the link other than `addr`
+    pub fn other_end(&self, addr: TerminalAddress) -> TerminalAddress {
+        if addr == self.src_addr {
+            self.dst_addr
+        } else {
+            self.src_addr
+        }
+    }
+
+    fn channel(&self, packet: Packet) -> &Channel {
+        match &self.reverse {
+            Some(reverse) if packet.hop_src == self.dst_addr => reverse,
+            _ => &self.forward,
+        }
+    }
+
+    fn mut_channel(&mut self, packet: Packet) -> &mut Channel {
+        match &mut self.reverse {
+            Some(reverse) if packet.hop_src == self.dst_addr => reverse,
+            _ => &mut self.forward,
+        }
+    }
+
+    /// Queues `packet` for transmission. Returns when it gets completely transmitted, unless
+    /// the queue drops it.
+    pub fn enqueue(&mut self, packet: Packet, now: Time, rng: &mut dyn RngCore) -> Option<Time> {
+        let (bytes, tx) = (packet.header_size + packet.payload_size, self.tx(packet));
+        let direction = self.direction(packet);
+
+        let medium = if self.half_duplex { 0 } else { direction };
+
+        let end = self.queues[direction].enqueue(bytes, tx, now, self.busy_until[medium], rng);
+        match end {
+            Some(end) => {
+                self.busy_until[medium] = end;
+                self.busy_time[direction] = self.busy_time[direction] + tx;
+            }
+            None => debug!("{} The queue drops {}", now.as_secs(), packet),
+        }
+
+        end
+    }
+
+    /// The packet as it reaches the other end, if it does
+    fn deliver<R: Rng>(&mut self, packet: Packet, now: Time, rng: &mut R) -> Option<Packet> {
+        if self
+            .loss_script
+            .as_mut()
+            .is_some_and(|script| script.drops(&packet))
+        {
+            debug!(
+                "{} Dropping {} as the loss script says",
+                now.as_secs(),
+                packet
+            );
+            return None;
+        }
+
+        let start = Time(now.0.saturating_sub(self.tx(packet).0));
+        let reception = match self
+            .mut_channel(packet)
+            .error_model
+            .reception(&packet, start, now, rng)
+        {
+            Reception::Lost => match self.checksum_miss {
+                Some(miss) if rng.gen::<f64>() < miss => Reception::Corrupted,
+                _ => Reception::Lost,
+            },
+            reception => reception,
+        };
+
+        match reception {
+            Reception::Intact => Some(packet),
+            Reception::Lost => None,
+            Reception::Corrupted => {
+                debug!(
+                    "{} {} got corrupted but the checksum missed it",
+                    now.as_secs(),
+                    packet
+                );
+                self.undetected += 1;
+                Some(Packet {
+                    corrupted: true,
+                    ..packet
+                })
+            }
+        }
+    }
+
+    /// Delay of `packet` once transmitted at `now`
+    fn propagation_delay<R: Rng>(&self, packet: Packet, now: Time, rng: &mut R) -> Time {
+        let delay = match &self.delay_trace {
+            Some(trace) => trace.delay(Time(now.0.saturating_sub(self.tx(packet).0))),
+            None => self.channel(packet).propagation_delay,
+        };
+
+        match &self.jitter {
+            Some(jitter) => delay + jitter.sample(rng),
+            None => delay,
+        }
+    }
+
+    /// Time when `packet` reaches the other end, keeping track of those that overtake others
+    fn arrival<R: Rng>(&mut self, packet: Packet, now: Time, rng: &mut R) -> Time {
+        let arrival = now + self.propagation_delay(packet, now, rng);
+        let direction = self.direction(packet);
+        let last_arrival = &mut self.last_arrival[direction];
+
+        let arrival = if arrival > *last_arrival {
+            arrival
+        } else if self.preserve_order {
+            Time(last_arrival.0 + 1)
+        } else {
+            debug!("{} {} overtakes an earlier packet", now.as_secs(), packet);
+            self.reordered += 1;
+            return arrival;
+        };
+        *last_arrival = arrival;
 
-        rng.gen::<f64>() > prob_tx
+        arrival
     }
 
     pub fn process<R: Rng>(&mut self, event: Event, now: Time, rng: &mut R) -> Vec<Event> {
         if let Payload(packet) = event.kind {
             self.counter = self.counter.transmitted_packet(packet);
 
-            if self.drop_packet(packet, rng) {
-                trace!("Packet got lost, sorry");
-                Vec::new()
-            } else {
-                self.counter = self.counter.delivered_packet(packet);
-                vec![
-                    (Event {
-                        due_time: now + self.propagation_delay,
-                        target: Target::Terminal(packet.dst_addr),
+            match self.deliver(packet, now, rng) {
+                None => {
+                    trace!("Packet got lost, sorry");
+                    Vec::new()
+                }
+                Some(packet) => {
+                    self.counter = self.counter.delivered_packet(packet);
+                    let mut res = vec![Event {
+                        due_time: self.arrival(packet, now, rng),
+                        target: Target::Node(self.other_end(packet.hop_src)),
                         kind: Payload(packet),
-                    }),
-                ]
+                    }];
+
+                    if self
+                        .duplication
+                        .is_some_and(|duplication| rng.gen::<f64>() < duplication)
+                    {
+                        // The copy follows right behind the original
+                        debug!("{} Duplicating {}", now.as_secs(), packet);
+                        self.duplicated += 1;
+                        self.counter = self.counter.delivered_packet(packet);
+                        res.push(Event {
+                            due_time: self.arrival(packet, now + self.tx(packet), rng),
+                            target: Target::Node(self.other_end(packet.hop_src)),
+                            kind: Payload(packet),
+                        });
+                    }
+
+                    res
+                }
             }
         } else {
             panic!("Link event with no attached packet to transmit")
@@ -106,18 +407,73 @@ impl AttachedLink {
     }
 
     pub fn tx(&self, packet: Packet) -> Time {
-        Time::from_secs(f64::from(8 * (packet.header_size + packet.payload_size)) / self.capacity)
+        Time::from_secs(
+            f64::from(8 * (packet.header_size + packet.payload_size))
+                / self.channel(packet).capacity,
+        )
     }
 
+    /// Time until `packet` gets acknowledged if the ACK, as long as the packet headers, is sent
+    /// right after receiving it
     pub fn calc_timeout(&self, packet: Packet) -> Time {
-        self.tx(Packet {
+        let ack = Packet {
             payload_size: 0,
+            src_addr: packet.dst_addr,
+            dst_addr: packet.src_addr,
+            hop_src: self.other_end(packet.hop_src),
             ..packet
-        }) + self.propagation_delay
-            + self.propagation_delay
+        };
+        // Be ready for the worst delays in the trace
+        let (delay, ack_delay) = match &self.delay_trace {
+            Some(trace) => (trace.max_delay(), trace.max_delay()),
+            None => (
+                self.channel(packet).propagation_delay,
+                self.channel(ack).propagation_delay,
+            ),
+        };
+
+        self.tx(ack) + delay + ack_delay
+    }
+
+    pub fn is_half_duplex(&self) -> bool {
+        self.half_duplex
+    }
+
+    /// Time spent transmitting packets sent by `addr`
+    pub fn busy_time(&self, addr: TerminalAddress) -> Time {
+        self.busy_time[usize::from(addr == self.dst_addr)]
+    }
+
+    /// Bytes put on the link in both directions, retransmissions included
+    pub fn transmitted_bytes(&self) -> u64 {
+        self.counter.raw_transmitted
     }
 
     pub fn show_stats(&self) {
+        for (queue, name) in self.queues.iter().zip(["Forward", "Reverse"]) {
+            if queue.is_managed() {
+                queue.show_stats(name);
+            }
+        }
+        self.forward.error_model.show_stats();
+        if let Some(reverse) = &self.reverse {
+            reverse.error_model.show_stats();
+        }
+        if let Some(script) = &self.loss_script {
+            script.show_stats();
+        }
+        if self.jitter.is_some() || self.delay_trace.is_some() {
+            println!("Reordered {} packets", self.reordered);
+        }
+        if self.duplication.is_some() {
+            println!("Duplicated {} packets", self.duplicated);
+        }
+        if self.undetected > 0 || self.checksum_miss.is_some() {
+            println!(
+                "Delivered {} corrupted packets the checksum missed",
+                self.undetected
+            );
+        }
         println!(
             "Transmitted {} bytes ({} of data)",
             self.counter.raw_transmitted, self.counter.good_transmitted
@@ -126,5 +482,36 @@ impl AttachedLink {
             "Delivered {} bytes ({} of data)",
             self.counter.raw_delivered, self.counter.good_delivered
         );
+        for kind in PacketKind::ALL {
+            let transmitted = self.counter.transmitted_packets(kind);
+            if transmitted > 0 {
+                println!(
+                    "{} packets: {} transmitted, {} delivered",
+                    kind,
+                    transmitted,
+                    self.counter.delivered_packets(kind)
+                );
+            }
+        }
+    }
+}
+
+/// Data packet of `bytes` for the tests of the error models
+#[cfg(test)]
+fn test_packet(bytes: u32) -> Packet {
+    use super::packet::SackBlocks;
+
+    Packet {
+        kind: PacketKind::Data,
+        seqno: 0,
+        abs_seqno: 0,
+        header_size: 0,
+        payload_size: bytes,
+        src_addr: Address::create(0),
+        dst_addr: Address::create(1),
+        hop_src: Address::create(0),
+        ack: None,
+        sack: SackBlocks::default(),
+        corrupted: false,
     }
 }
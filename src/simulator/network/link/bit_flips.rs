@@ -0,0 +1,269 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::error_model::{bits, ErrorModel, Reception};
+use crate::simulator::network::packet::Packet;
+use clap::ValueEnum;
+use eee_hyst::Time;
+use rand::{Rng, RngCore};
+
+/// Error detection code carried at the end of every frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Checksum {
+    /// CRC-16 of HDLC and X.25
+    Crc16,
+    /// CRC-32 of Ethernet
+    Crc32,
+    /// 16 bit ones' complement sum of IP, TCP and UDP
+    Internet,
+}
+
+impl Checksum {
+    pub const ALL: [Checksum; 3] = [Checksum::Crc16, Checksum::Crc32, Checksum::Internet];
+
+    /// Bytes taken from the header
+    fn len(self) -> usize {
+        match self {
+            Checksum::Crc16 | Checksum::Internet => 2,
+            Checksum::Crc32 => 4,
+        }
+    }
+
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            Checksum::Crc16 => crc(data, 0x8408, 0xffff),
+            Checksum::Crc32 => crc(data, 0xedb8_8320, 0xffff_ffff),
+            Checksum::Internet => {
+                // Folding the carries back at every step keeps the sum within 16 bits
+                let sum = data
+                    .chunks(2)
+                    .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+                    .fold(0, |sum, word| {
+                        let sum = sum + word;
+                        (sum & 0xffff) + (sum >> 16)
+                    });
+
+                !sum & 0xffff
+            }
+        }
+    }
+
+    /// The checksum of `data` as sent on the wire
+    fn trailer(self, data: &[u8]) -> Vec<u8> {
+        let value = self.compute(data);
+        match self {
+            Checksum::Crc16 => value.to_le_bytes()[..2].to_vec(),
+            Checksum::Crc32 => value.to_le_bytes().to_vec(),
+            Checksum::Internet => value.to_be_bytes()[2..].to_vec(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Checksum::Crc16 => "CRC-16",
+            Checksum::Crc32 => "CRC-32",
+            Checksum::Internet => "Internet checksum",
+        }
+    }
+}
+
+/// Reflected CRC, with the final value inverted as in both HDLC and Ethernet
+fn crc(data: &[u8], poly: u32, mask: u32) -> u32 {
+    let mut crc = mask;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc & mask
+}
+
+/// Independent bit errors on actual frame contents. Frames end with the checksum of the rest
+/// and only those whose checksum does not match get discarded. Every code gets checked
+/// against the same errors, to compare how many go unnoticed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BitFlips {
+    ber: f64,
+    checksum: Checksum,
+    corrupted: u64,
+    undetected: [u64; 3], // For every code in Checksum::ALL
+}
+
+impl BitFlips {
+    pub fn create(ber: f64, checksum: Checksum) -> BitFlips {
+        BitFlips {
+            ber,
+            checksum,
+            corrupted: 0,
+            undetected: [0; 3],
+        }
+    }
+
+    /// Positions of the bits that get flipped among the first `bits`
+    fn flips(&self, bits: u64, rng: &mut dyn RngCore) -> Vec<u64> {
+        let mut flips = Vec::new();
+        if self.ber <= 0.0 {
+            return flips;
+        }
+
+        // Jump straight to the next error, as the gaps between them are geometric
+        let mut next = 0.0;
+        loop {
+            next += if self.ber >= 1.0 {
+                0.0
+            } else {
+                ((1.0 - rng.gen::<f64>()).ln() / (1.0 - self.ber).ln()).floor()
+            };
+            if next >= bits as f64 {
+                return flips;
+            }
+            flips.push(next as u64);
+            next += 1.0;
+        }
+    }
+
+    /// Tells whether `checksum` notices the errors at `flips` in `frame`
+    fn detects(checksum: Checksum, frame: &[u8], flips: &[u64]) -> bool {
+        if frame.len() <= checksum.len() {
+            return true;
+        }
+
+        let (data, _) = frame.split_at(frame.len() - checksum.len());
+        let mut sent = data.to_vec();
+        sent.extend(checksum.trailer(data));
+
+        let mut received = sent;
+        for &flip in flips {
+            received[(flip / 8) as usize] ^= 1 << (flip % 8);
+        }
+        let (data, trailer) = received.split_at(received.len() - checksum.len());
+
+        checksum.trailer(data) != trailer
+    }
+}
+
+impl ErrorModel for BitFlips {
+    fn is_lost(&mut self, packet: &Packet, start: Time, end: Time, rng: &mut dyn RngCore) -> bool {
+        self.reception(packet, start, end, rng) != Reception::Intact
+    }
+
+    fn reception(&mut self, packet: &Packet, _: Time, _: Time, rng: &mut dyn RngCore) -> Reception {
+        let flips = self.flips(bits(packet), rng);
+        if flips.is_empty() {
+            return Reception::Intact;
+        }
+        self.corrupted += 1;
+
+        let mut frame = vec![0; (bits(packet) / 8) as usize];
+        rng.fill_bytes(&mut frame);
+
+        let mut detected = true;
+        for (checksum, undetected) in Checksum::ALL.iter().zip(self.undetected.iter_mut()) {
+            if !BitFlips::detects(*checksum, &frame, &flips) {
+                *undetected += 1;
+                detected &= *checksum != self.checksum;
+            }
+        }
+
+        if detected {
+            Reception::Lost
+        } else {
+            Reception::Corrupted
+        }
+    }
+
+    fn show_stats(&self) {
+        println!("{} frames got bit errors", self.corrupted);
+        for (checksum, undetected) in Checksum::ALL.iter().zip(self.undetected) {
+            println!(
+                "{} would have missed {} of them (residual rate {}){}",
+                checksum.name(),
+                undetected,
+                if self.corrupted > 0 {
+                    undetected as f64 / self.corrupted as f64
+                } else {
+                    0.0
+                },
+                if *checksum == self.checksum {
+                    ", in use"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(Checksum::Crc32.compute(CHECK), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        // CRC-16/X.25
+        assert_eq!(Checksum::Crc16.compute(CHECK), 0x906e);
+    }
+
+    #[test]
+    fn internet_checksum_reference() {
+        // Example from RFC 1071, section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+
+        assert_eq!(Checksum::Internet.compute(&data), !0xddf2 & 0xffff);
+    }
+
+    #[test]
+    fn internet_checksum_of_large_frames() {
+        assert_eq!(Checksum::Internet.compute(&vec![0xff; 400_000]), 0);
+        // 200000 words of 1 add up to 0x30d40
+        let data = [0x00, 0x01].repeat(200_000);
+        assert_eq!(Checksum::Internet.compute(&data), !0x0d43 & 0xffff);
+    }
+
+    #[test]
+    fn single_bit_flips_are_always_detected() {
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let mut frame = vec![0; 64];
+        rng.fill_bytes(&mut frame);
+
+        for checksum in Checksum::ALL {
+            for flip in 0..8 * frame.len() as u64 {
+                assert!(
+                    BitFlips::detects(checksum, &frame, &[flip]),
+                    "{} missed a flip of bit {}",
+                    checksum.name(),
+                    flip
+                );
+            }
+        }
+    }
+}
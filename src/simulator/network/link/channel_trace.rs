@@ -0,0 +1,136 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::error_model::LossTrace;
+use eee_hyst::Time;
+
+/// When the conditions measured over a real channel change. Each record holds from its
+/// timestamp until the next one, and the recording starts over once the simulation outlasts
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Timeline {
+    times: Vec<Time>, // Since the first record
+    period: Time,
+}
+
+impl Timeline {
+    /// Position of the record in force for a packet sent at `sent`
+    pub(super) fn record(&self, sent: Time) -> usize {
+        let time = sent.0 % self.period.0;
+
+        self.times.partition_point(|record| record.0 <= time).max(1) - 1
+    }
+}
+
+/// Replays the propagation delays measured over a real channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DelayTrace {
+    timeline: Timeline,
+    delays: Vec<Time>,
+}
+
+impl DelayTrace {
+    /// Propagation delay of a packet sent at `sent`
+    pub fn delay(&self, sent: Time) -> Time {
+        self.delays[self.timeline.record(sent)]
+    }
+
+    pub fn max_delay(&self) -> Time {
+        self.delays.iter().copied().max().unwrap_or(Time(0))
+    }
+}
+
+/// Losses and propagation delays measured over a real channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelTrace {
+    timeline: Timeline,
+    lost: Vec<bool>,
+    delays: Vec<Time>,
+}
+
+impl ChannelTrace {
+    /// Reads a CSV trace with a `time,lost,delay` record per line, in seconds, where `lost` is
+    /// 1 for lost packets and 0 otherwise. A header line and anything after a # are ignored.
+    pub fn parse(trace: &str) -> Result<ChannelTrace, String> {
+        let (mut times, mut lost, mut delays): (Vec<Time>, Vec<bool>, Vec<Time>) =
+            (Vec::new(), Vec::new(), Vec::new());
+        let mut start = None;
+
+        for (n, line) in trace.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || (n == 0 && line.starts_with(char::is_alphabetic)) {
+                continue;
+            }
+
+            let fields = line.split(',').map(str::trim).collect::<Vec<&str>>();
+            let (time, loss, delay) = match fields[..] {
+                [time, loss, delay] => (time, loss, delay),
+                _ => return Err(format!("Expected time,lost,delay in line {}", n + 1)),
+            };
+            let secs = |field: &str| match field.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 => Ok(Time::from_secs(secs)),
+                _ => Err(format!("Invalid time {} in line {}", field, n + 1)),
+            };
+
+            let time = secs(time)?;
+            let start = *start.get_or_insert(time);
+            times.push(match times.last() {
+                Some(last) if time.0 < start.0 + last.0 => {
+                    return Err(format!("Time goes backwards in line {}", n + 1))
+                }
+                _ => Time(time.0 - start.0),
+            });
+            lost.push(match loss {
+                "0" => false,
+                "1" => true,
+                _ => return Err(format!("Invalid loss flag {} in line {}", loss, n + 1)),
+            });
+            delays.push(secs(delay)?);
+        }
+
+        // The last record lasts as long as the average one
+        let span = match times.last() {
+            Some(last) => last.0,
+            None => return Err("Empty channel trace".to_string()),
+        };
+        let period = match times.len() {
+            1 => 1,
+            n => span + span / (n as u64 - 1),
+        };
+
+        Ok(ChannelTrace {
+            timeline: Timeline {
+                times,
+                period: Time(period.max(1)),
+            },
+            lost,
+            delays,
+        })
+    }
+
+    /// Error model that drops the packets sent while the trace shows losses
+    pub fn losses(&self) -> LossTrace {
+        LossTrace::timed(self.timeline.clone(), self.lost.clone())
+    }
+
+    pub fn delays(&self) -> DelayTrace {
+        DelayTrace {
+            timeline: self.timeline.clone(),
+            delays: self.delays.clone(),
+        }
+    }
+}
@@ -15,7 +15,7 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use crate::simulator::network::packet::Packet;
+use crate::simulator::network::packet::{Packet, PacketKind};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct DataCounter {
@@ -23,24 +23,42 @@ pub struct DataCounter {
     pub good_transmitted: u64,
     pub raw_delivered: u64,
     pub good_delivered: u64,
+    transmitted_packets: [u64; PacketKind::ALL.len()],
+    delivered_packets: [u64; PacketKind::ALL.len()],
 }
 
 impl DataCounter {
     pub fn transmitted_packet(&self, packet: Packet) -> DataCounter {
+        let mut transmitted_packets = self.transmitted_packets;
+        transmitted_packets[packet.kind as usize] += 1;
+
         DataCounter {
             raw_transmitted: self.raw_transmitted + raw(packet),
             good_transmitted: self.good_transmitted + good(packet),
+            transmitted_packets,
             ..*self
         }
     }
 
     pub fn delivered_packet(&self, packet: Packet) -> DataCounter {
+        let mut delivered_packets = self.delivered_packets;
+        delivered_packets[packet.kind as usize] += 1;
+
         DataCounter {
             raw_delivered: self.raw_delivered + raw(packet),
             good_delivered: self.good_delivered + good(packet),
+            delivered_packets,
             ..*self
         }
     }
+
+    pub fn transmitted_packets(&self, kind: PacketKind) -> u64 {
+        self.transmitted_packets[kind as usize]
+    }
+
+    pub fn delivered_packets(&self, kind: PacketKind) -> u64 {
+        self.delivered_packets[kind as usize]
+    }
 }
 
 fn raw(packet: Packet) -> u64 {
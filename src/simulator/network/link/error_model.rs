@@ -0,0 +1,347 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::channel_trace::Timeline;
+use crate::simulator::network::packet::Packet;
+use eee_hyst::Time;
+use rand::{Rng, RngCore};
+use std::fmt::Debug;
+
+/// How a packet reaches the other end of a link
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reception {
+    Intact,
+    Lost,      // With errors noticed by the receiver
+    Corrupted, // With errors that went unnoticed
+}
+
+/// Decides which packets get corrupted on a link
+pub trait ErrorModel: ErrorModelClone + Debug {
+    /// Tells whether `packet`, transmitted from `start` to `end`, arrives with errors
+    fn is_lost(&mut self, packet: &Packet, start: Time, end: Time, rng: &mut dyn RngCore) -> bool;
+
+    /// Like `is_lost`, for models that can tell whether the receiver notices the errors. The
+    /// rest just assume it always does.
+    fn reception(
+        &mut self,
+        packet: &Packet,
+        start: Time,
+        end: Time,
+        rng: &mut dyn RngCore,
+    ) -> Reception {
+        if self.is_lost(packet, start, end, rng) {
+            Reception::Lost
+        } else {
+            Reception::Intact
+        }
+    }
+
+    fn show_stats(&self) {}
+}
+
+/// Lets links holding a boxed model be cloned. Implemented for every model that is `Clone`.
+pub trait ErrorModelClone {
+    fn box_clone(&self) -> Box<dyn ErrorModel>;
+}
+
+impl<T: ErrorModel + Clone + 'static> ErrorModelClone for T {
+    fn box_clone(&self) -> Box<dyn ErrorModel> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn ErrorModel> {
+    fn clone(&self) -> Self {
+        (**self).box_clone()
+    }
+}
+
+/// Lets a model picked at run time go wherever a concrete one is expected
+impl ErrorModel for Box<dyn ErrorModel> {
+    fn is_lost(&mut self, packet: &Packet, start: Time, end: Time, rng: &mut dyn RngCore) -> bool {
+        (**self).is_lost(packet, start, end, rng)
+    }
+
+    fn reception(
+        &mut self,
+        packet: &Packet,
+        start: Time,
+        end: Time,
+        rng: &mut dyn RngCore,
+    ) -> Reception {
+        (**self).reception(packet, start, end, rng)
+    }
+
+    fn show_stats(&self) {
+        (**self).show_stats()
+    }
+}
+
+pub(super) fn bits(packet: &Packet) -> u64 {
+    8 * u64::from(packet.header_size + packet.payload_size)
+}
+
+/// Independent bit errors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BitErrors {
+    ber: f64,
+}
+
+impl BitErrors {
+    pub fn create(ber: f64) -> BitErrors {
+        BitErrors { ber }
+    }
+}
+
+impl ErrorModel for BitErrors {
+    fn is_lost(&mut self, packet: &Packet, _: Time, _: Time, rng: &mut dyn RngCore) -> bool {
+        let prob_tx = (1.0 - self.ber).powi(i32::try_from(bits(packet)).unwrap());
+
+        rng.gen::<f64>() > prob_tx
+    }
+}
+
+/// Every packet is lost with the same probability, whatever its length
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketErrors {
+    per: f64,
+}
+
+impl PacketErrors {
+    pub fn create(per: f64) -> PacketErrors {
+        PacketErrors { per }
+    }
+}
+
+impl ErrorModel for PacketErrors {
+    fn is_lost(&mut self, _: &Packet, _: Time, _: Time, rng: &mut dyn RngCore) -> bool {
+        rng.gen::<f64>() < self.per
+    }
+}
+
+/// Loses packets following a pattern that repeats forever
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LossPattern {
+    pattern: Vec<bool>,
+    next: usize,
+}
+
+impl LossPattern {
+    /// Parses a pattern such as "0001", where every 1 marks a lost packet
+    pub fn parse(pattern: &str) -> Option<LossPattern> {
+        let pattern = pattern
+            .chars()
+            .map(|c| match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect::<Option<Vec<bool>>>()?;
+
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(LossPattern { pattern, next: 0 })
+    }
+}
+
+impl ErrorModel for LossPattern {
+    fn is_lost(&mut self, _: &Packet, _: Time, _: Time, _: &mut dyn RngCore) -> bool {
+        let lost = self.pattern[self.next];
+        self.next = (self.next + 1) % self.pattern.len();
+
+        lost
+    }
+}
+
+/// Replays the fate of the packets sent over a real channel, either packet by packet, then
+/// losing no more, or following the timeline of a channel trace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LossTrace {
+    trace: Vec<bool>,
+    timeline: Option<Timeline>, // None to go packet by packet
+    next: usize,
+    lost: u64,
+}
+
+impl LossTrace {
+    /// Reads a trace with a line per packet, holding 1 if it was lost and 0 otherwise
+    pub fn parse(trace: &str) -> Option<LossTrace> {
+        let trace = trace
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match line {
+                "0" => Some(false),
+                "1" => Some(true),
+                _ => None,
+            })
+            .collect::<Option<Vec<bool>>>()?;
+
+        Some(LossTrace {
+            trace,
+            timeline: None,
+            next: 0,
+            lost: 0,
+        })
+    }
+
+    /// Loses the packets sent while the record in force in `timeline` says so
+    pub(super) fn timed(timeline: Timeline, trace: Vec<bool>) -> LossTrace {
+        LossTrace {
+            trace,
+            timeline: Some(timeline),
+            next: 0,
+            lost: 0,
+        }
+    }
+}
+
+impl ErrorModel for LossTrace {
+    fn is_lost(&mut self, _: &Packet, start: Time, _: Time, _: &mut dyn RngCore) -> bool {
+        let lost = match &self.timeline {
+            Some(timeline) => self.trace[timeline.record(start)],
+            None => {
+                self.next += 1;
+                self.trace.get(self.next - 1).copied().unwrap_or(false)
+            }
+        };
+        if lost {
+            self.lost += 1;
+        }
+
+        lost
+    }
+
+    fn show_stats(&self) {
+        if self.timeline.is_some() {
+            println!(
+                "Dropped {} packets replaying a channel trace of {} records",
+                self.lost,
+                self.trace.len()
+            );
+        } else if self.next > self.trace.len() {
+            println!(
+                "Loss trace exhausted after {} packets, the rest went through",
+                self.trace.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{test_packet as packet, ChannelTrace};
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    const SAMPLES: u32 = 100_000;
+
+    /// Fraction of SAMPLES packets of `bytes` that `model` loses
+    fn loss_rate(model: &mut dyn ErrorModel, bytes: u32) -> f64 {
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let lost = (0..SAMPLES)
+            .filter(|_| model.is_lost(&packet(bytes), Time(0), Time(0), &mut rng))
+            .count();
+
+        lost as f64 / f64::from(SAMPLES)
+    }
+
+    /// Checks that `rate` is within four standard deviations of the `expected` probability
+    fn assert_rate(rate: f64, expected: f64) {
+        let margin = 4.0 * (expected * (1.0 - expected) / f64::from(SAMPLES)).sqrt();
+        assert!(
+            (rate - expected).abs() <= margin,
+            "loss rate {} too far from {}",
+            rate,
+            expected
+        );
+    }
+
+    /// Outcomes of the next `n` packets
+    fn losses(model: &mut dyn ErrorModel, n: usize) -> Vec<bool> {
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        (0..n)
+            .map(|_| model.is_lost(&packet(1000), Time(0), Time(0), &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn bit_errors_depend_on_the_length() {
+        let ber = 1e-5;
+        for bytes in [40, 1500] {
+            assert_rate(
+                loss_rate(&mut BitErrors::create(ber), bytes),
+                1.0 - (1.0 - ber).powi(8 * bytes as i32),
+            );
+        }
+    }
+
+    #[test]
+    fn bit_errors_without_errors() {
+        assert_eq!(loss_rate(&mut BitErrors::create(0.0), 1500), 0.0);
+        assert_eq!(loss_rate(&mut BitErrors::create(1.0), 1), 1.0);
+    }
+
+    #[test]
+    fn packet_errors_ignore_the_length() {
+        for bytes in [40, 1500] {
+            assert_rate(loss_rate(&mut PacketErrors::create(0.1), bytes), 0.1);
+        }
+    }
+
+    #[test]
+    fn loss_pattern_repeats() {
+        let mut pattern = LossPattern::parse("0010").unwrap();
+
+        assert_eq!(
+            losses(&mut pattern, 9),
+            [false, false, true, false, false, false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn loss_pattern_rejects_other_symbols() {
+        assert!(LossPattern::parse("").is_none());
+        assert!(LossPattern::parse("0120").is_none());
+    }
+
+    #[test]
+    fn loss_trace_replays_every_packet_then_stops() {
+        let mut trace = LossTrace::parse("1\n0\n\n 1\n0\n").unwrap();
+
+        assert_eq!(
+            losses(&mut trace, 6),
+            [true, false, true, false, false, false]
+        );
+        assert!(LossTrace::parse("1\n2\n").is_none());
+    }
+
+    #[test]
+    fn loss_trace_follows_the_channel_trace_timeline() {
+        let trace = ChannelTrace::parse("time,lost,delay\n1,0,0\n1.5,1,0\n2,0,0\n").unwrap();
+        let mut losses = trace.losses();
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+
+        // It starts over after the last record, which lasts 0.5 s like the rest
+        let lost = [0.0, 0.4, 0.5, 0.9, 1.0, 1.4, 1.5, 2.0]
+            .map(|secs| losses.is_lost(&packet(1000), Time::from_secs(secs), Time(0), &mut rng));
+        assert_eq!(lost, [false, false, true, true, false, false, false, true]);
+    }
+}
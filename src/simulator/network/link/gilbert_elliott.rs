@@ -0,0 +1,193 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::error_model::{bits, ErrorModel};
+use crate::simulator::network::packet::Packet;
+use eee_hyst::Time;
+use rand::{Rng, RngCore};
+use std::cmp::max;
+
+type Matrix = [[f64; 2]; 2];
+
+const GOOD: usize = 0;
+const BAD: usize = 1;
+
+/// Two-state burst error channel. It stays in each state for an exponentially distributed
+/// time, and bit errors within a state are independent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GilbertElliott {
+    ber: [f64; 2],
+    rate: [f64; 2], // Rate of leaving each state, in 1/s
+    state: usize,
+    last_update: Time,
+}
+
+impl GilbertElliott {
+    /// `mean_good` and `mean_bad` are the average times spent in each state, in seconds
+    pub fn create(ber_good: f64, ber_bad: f64, mean_good: f64, mean_bad: f64) -> GilbertElliott {
+        GilbertElliott {
+            ber: [ber_good, ber_bad],
+            rate: [1.0 / mean_good, 1.0 / mean_bad],
+            state: GOOD,
+            last_update: Time(0),
+        }
+    }
+
+    /// Long term fraction of time in the bad state
+    fn bad_share(&self) -> f64 {
+        self.rate[GOOD] / (self.rate[GOOD] + self.rate[BAD])
+    }
+
+    /// Long term bit error rate
+    pub fn average_ber(&self) -> f64 {
+        let bad_share = self.bad_share();
+
+        (1.0 - bad_share) * self.ber[GOOD] + bad_share * self.ber[BAD]
+    }
+
+    /// State transition probabilities after `secs` seconds
+    fn transitions(&self, secs: f64) -> Matrix {
+        let bad_share = self.bad_share();
+        let mixing = 1.0 - (-(self.rate[GOOD] + self.rate[BAD]) * secs).exp();
+
+        [
+            [1.0 - bad_share * mixing, bad_share * mixing],
+            [(1.0 - bad_share) * mixing, 1.0 - (1.0 - bad_share) * mixing],
+        ]
+    }
+}
+
+impl ErrorModel for GilbertElliott {
+    /// Moves the channel along until `start`, then decides whether the packet sent until `end`
+    /// arrives without errors, leaving the channel in a state consistent with that outcome
+    fn is_lost(&mut self, packet: &Packet, start: Time, end: Time, rng: &mut dyn RngCore) -> bool {
+        let bits = bits(packet);
+        if start > self.last_update {
+            let idle = self.transitions(Time(start.0 - self.last_update.0).as_secs());
+            self.state = next_state(idle[self.state][BAD], rng);
+        }
+        // Both directions share the channel, so packets may overlap
+        let start = max(start, self.last_update);
+        let duration = Time(max(start, end).0 - start.0).as_secs();
+        self.last_update = max(start, end);
+
+        if bits == 0 {
+            return false;
+        }
+
+        // Each bit goes through with the error rate of the current state, then the state may change
+        let mut per_bit = self.transitions(duration / bits as f64);
+        for (state, row) in per_bit.iter_mut().enumerate() {
+            for p in row.iter_mut() {
+                *p *= 1.0 - self.ber[state];
+            }
+        }
+        let survive = power(per_bit, bits)[self.state];
+        let survive_prob = survive[GOOD] + survive[BAD];
+        let bad_prob = self.transitions(duration)[self.state][BAD];
+
+        if rng.gen::<f64>() < survive_prob {
+            self.state = next_state(survive[BAD] / survive_prob, rng);
+            false
+        } else {
+            self.state = next_state((bad_prob - survive[BAD]) / (1.0 - survive_prob), rng);
+            true
+        }
+    }
+
+    fn show_stats(&self) {
+        println!(
+            "Gilbert–Elliott channel with an average BER of {}",
+            self.average_ber()
+        );
+    }
+}
+
+fn next_state(bad_prob: f64, rng: &mut dyn RngCore) -> usize {
+    if rng.gen::<f64>() < bad_prob {
+        BAD
+    } else {
+        GOOD
+    }
+}
+
+fn multiply(a: Matrix, b: Matrix) -> Matrix {
+    let mut res = [[0.0; 2]; 2];
+    for (i, row) in res.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+        }
+    }
+
+    res
+}
+
+fn power(mut base: Matrix, mut exp: u64) -> Matrix {
+    let mut res = [[1.0, 0.0], [0.0, 1.0]];
+    while exp > 0 {
+        if exp & 1 == 1 {
+            res = multiply(res, base);
+        }
+        base = multiply(base, base);
+        exp >>= 1;
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_packet as packet;
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn average_ber_weights_each_state() {
+        let channel = GilbertElliott::create(1e-6, 1e-3, 0.01, 0.002);
+
+        assert!((channel.average_ber() - (5.0 * 1e-6 + 1e-3) / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn loss_rate_matches_the_stationary_average() {
+        let (ber, mean) = ([1e-6, 1e-2], [0.01, 0.002]);
+        let mut channel = GilbertElliott::create(ber[GOOD], ber[BAD], mean[GOOD], mean[BAD]);
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+
+        // Short packets, far apart, so that each one finds the channel in its stationary state
+        let (samples, bits) = (200_000, 800);
+        let lost = (0..samples)
+            .filter(|n| {
+                let start = Time::from_secs(1e-3 * f64::from(*n));
+                let end = start + Time::from_secs(1e-5);
+                channel.is_lost(&packet(bits / 8), start, end, &mut rng)
+            })
+            .count();
+
+        let bad_share = mean[BAD] / (mean[GOOD] + mean[BAD]);
+        let expected = (1.0 - bad_share) * (1.0 - (1.0 - ber[GOOD]).powi(bits as i32))
+            + bad_share * (1.0 - (1.0 - ber[BAD]).powi(bits as i32));
+        let rate = lost as f64 / f64::from(samples);
+        assert!(
+            (rate - expected).abs() < 0.05 * expected,
+            "loss rate {} too far from {}",
+            rate,
+            expected
+        );
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use eee_hyst::Time;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Longest jitter, in seconds, so that heavy tails cannot push arrivals out of the time range
+const MAX_JITTER: f64 = 60.0;
+
+/// Smallest Pareto shape, which keeps the mean jitter finite
+const MIN_PARETO_SHAPE: f64 = 1.0;
+
+/// Random delay added to the propagation delay of every packet. All values are in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jitter {
+    Uniform {
+        max: f64,
+    },
+    /// Negative samples become zero
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+    Exponential {
+        mean: f64,
+    },
+    /// Heavy tailed, never below `scale`. The shape has to be above one.
+    Pareto {
+        scale: f64,
+        shape: f64,
+    },
+}
+
+impl Jitter {
+    /// Parses a distribution such as `uniform:MAX`, `normal:MEAN,STD_DEV`, `exponential:MEAN`
+    /// or `pareto:SCALE,SHAPE`
+    pub fn parse(jitter: &str) -> Result<Jitter, String> {
+        let (name, params) = jitter.split_once(':').unwrap_or((jitter, ""));
+        let params = params
+            .split(',')
+            .map(|param| param.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("Invalid parameters in {}", jitter))?;
+
+        if params
+            .iter()
+            .any(|&param| !param.is_finite() || param < 0.0)
+        {
+            return Err(format!(
+                "Parameters have to be finite and positive in {}",
+                jitter
+            ));
+        }
+
+        match (name.trim().to_lowercase().as_str(), &params[..]) {
+            ("uniform", &[max]) => Ok(Jitter::Uniform { max }),
+            ("normal", &[mean, std_dev]) => Ok(Jitter::Normal { mean, std_dev }),
+            ("exponential", &[mean]) => Ok(Jitter::Exponential { mean }),
+            ("pareto", &[scale, shape]) if shape > MIN_PARETO_SHAPE => {
+                Ok(Jitter::Pareto { scale, shape })
+            }
+            _ => Err(format!(
+                "Expected uniform:MAX, normal:MEAN,STD_DEV, exponential:MEAN or pareto:SCALE,SHAPE with SHAPE > 1, found {}",
+                jitter
+            )),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Time {
+        // In (0, 1], so that the logarithms below stay finite
+        let mut uniform = || 1.0 - rng.gen::<f64>();
+
+        let secs = match *self {
+            Jitter::Uniform { max } => max * (1.0 - uniform()),
+            Jitter::Normal { mean, std_dev } => {
+                // Box–Muller transform
+                let (u1, u2) = (uniform(), uniform());
+                mean + std_dev * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+            }
+            Jitter::Exponential { mean } => -mean * uniform().ln(),
+            Jitter::Pareto { scale, shape } => scale / uniform().powf(1.0 / shape),
+        };
+
+        Time::from_secs(secs.clamp(0.0, MAX_JITTER))
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::simulator::network::packet::{Packet, PacketKind};
+
+/// Drops the `occurrence`-th packet among those matching every given field
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LossRule {
+    kind: Option<PacketKind>,
+    seqno: Option<u64>, // The one known to the simulator, not the one on the wire
+    src_addr: Option<usize>,
+    occurrence: u64,
+    seen: u64, // Matching packets so far
+}
+
+impl LossRule {
+    fn parse(rule: &str) -> Result<LossRule, String> {
+        let mut res = LossRule {
+            kind: None,
+            seqno: None,
+            src_addr: None,
+            occurrence: 1,
+            seen: 0,
+        };
+
+        for field in rule.split_whitespace() {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value, found {}", field))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid number in {}", field))
+            };
+
+            match key {
+                "kind" => {
+                    res.kind = Some(match value.to_lowercase().as_str() {
+                        "data" => PacketKind::Data,
+                        "ack" => PacketKind::Ack,
+                        "nak" => PacketKind::Nak,
+                        "syn" => PacketKind::Syn,
+                        "fin" => PacketKind::Fin,
+                        _ => return Err(format!("Unknown packet kind {}", value)),
+                    })
+                }
+                "seqno" => res.seqno = Some(number()?),
+                "from" => res.src_addr = Some(number()? as usize),
+                "n" => match number()? {
+                    0 => return Err(format!("Occurrences start at 1 in {}", field)),
+                    n => res.occurrence = n,
+                },
+                _ => return Err(format!("Unknown field {}", field)),
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn matches(&self, packet: &Packet) -> bool {
+        self.kind.is_none_or(|kind| kind == packet.kind)
+            && self.seqno.is_none_or(|seqno| seqno == packet.abs_seqno)
+            && self
+                .src_addr
+                .is_none_or(|addr| addr == usize::from(packet.src_addr))
+    }
+}
+
+/// Packets to drop no matter what the error model says, so that every run shows the same
+/// timeline. Rules are separated by semicolons or new lines and made of key=value fields:
+/// `kind` (data, ack, nak, syn or fin), `seqno`, `from` (source terminal address) and `n`, to
+/// drop the n-th matching packet instead of the first one. For instance, `kind=data n=3;
+/// kind=ack n=5` drops the third data packet and the fifth ACK. Anything after a # is ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LossScript {
+    rules: Vec<LossRule>,
+    dropped: u64,
+}
+
+impl LossScript {
+    pub fn parse(script: &str) -> Result<LossScript, String> {
+        let rules = script
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(';'))
+            .filter(|rule| !rule.trim().is_empty())
+            .map(LossRule::parse)
+            .collect::<Result<Vec<LossRule>, String>>()?;
+
+        Ok(LossScript { rules, dropped: 0 })
+    }
+
+    /// Tells whether `packet` has to be dropped. Every packet sent over the link must go
+    /// through here for the occurrences to be counted right.
+    pub fn drops(&mut self, packet: &Packet) -> bool {
+        let mut drop = false;
+        for rule in self.rules.iter_mut().filter(|rule| rule.matches(packet)) {
+            rule.seen += 1;
+            drop |= rule.seen == rule.occurrence;
+        }
+
+        if drop {
+            self.dropped += 1;
+        }
+
+        drop
+    }
+
+    pub fn show_stats(&self) {
+        println!("Dropped {} packets following the loss script", self.dropped);
+    }
+}
@@ -0,0 +1,377 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use eee_hyst::Time;
+use rand::{Rng, RngCore};
+use std::cmp::max;
+use std::collections::VecDeque;
+
+/// Maximum queue occupancy, counting the packet being transmitted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueLimit {
+    Packets(u64),
+    Bytes(u64),
+}
+
+fn parse_params(text: &str, name: &str) -> Result<Vec<f64>, String> {
+    let params = text
+        .split(',')
+        .map(|param| param.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| format!("Invalid {} parameters {}", name, text))?;
+
+    if params
+        .iter()
+        .any(|&param| !param.is_finite() || param < 0.0)
+    {
+        return Err(format!(
+            "{} parameters have to be finite and positive in {}",
+            name, text
+        ));
+    }
+
+    Ok(params)
+}
+
+/// Random Early Detection, as described by Floyd and Jacobson
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Red {
+    min_threshold: f64, // Average queue length, in packets
+    max_threshold: f64,
+    max_prob: f64,
+    weight: f64,
+    average: f64,
+    count: i64, // Packets since the last drop, -1 while below the minimum threshold
+}
+
+impl Red {
+    pub fn create(min_threshold: f64, max_threshold: f64, max_prob: f64, weight: f64) -> Red {
+        Red {
+            min_threshold,
+            max_threshold,
+            max_prob,
+            weight,
+            average: 0.0,
+            count: -1,
+        }
+    }
+
+    /// Parses `MIN,MAX[,MAX_PROB[,WEIGHT]]`, with a maximum probability of 0.1 and a weight of
+    /// 0.002 unless told otherwise
+    pub fn parse(red: &str) -> Result<Red, String> {
+        match parse_params(red, "RED")?[..] {
+            [min, max, ref rest @ ..] if min < max && rest.len() <= 2 => Ok(Red::create(
+                min,
+                max,
+                rest.first().copied().unwrap_or(0.1),
+                rest.get(1).copied().unwrap_or(0.002),
+            )),
+            _ => Err(format!(
+                "Expected MIN,MAX[,MAX_PROB[,WEIGHT]] with MIN < MAX, found {}",
+                red
+            )),
+        }
+    }
+
+    /// Decides on arrival whether to drop a packet that finds `packets` in the queue, which
+    /// may have been idle for `idle_slots` transmission times
+    fn drops(&mut self, packets: u64, idle_slots: f64, rng: &mut dyn RngCore) -> bool {
+        self.average = if packets == 0 {
+            self.average * (1.0 - self.weight).powf(idle_slots)
+        } else {
+            (1.0 - self.weight) * self.average + self.weight * packets as f64
+        };
+
+        if self.average >= self.max_threshold {
+            self.count = 0;
+            return true;
+        }
+        if self.average < self.min_threshold {
+            self.count = -1;
+            return false;
+        }
+
+        self.count += 1;
+        let prob = self.max_prob * (self.average - self.min_threshold)
+            / (self.max_threshold - self.min_threshold);
+        let prob = if self.count as f64 * prob >= 1.0 {
+            1.0
+        } else {
+            prob / (1.0 - self.count as f64 * prob)
+        };
+
+        if rng.gen::<f64>() < prob {
+            self.count = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Controlled Delay, as in RFC 8289. It drops packets when they leave the queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoDel {
+    target: Time,
+    interval: Time,
+    first_above: Option<Time>,
+    dropping: bool,
+    drop_next: Time,
+    count: u64,
+    last_count: u64,
+}
+
+impl CoDel {
+    pub fn create(target: Time, interval: Time) -> CoDel {
+        CoDel {
+            target,
+            interval,
+            first_above: None,
+            dropping: false,
+            drop_next: Time(0),
+            count: 0,
+            last_count: 0,
+        }
+    }
+
+    /// Parses `TARGET,INTERVAL`, in seconds
+    pub fn parse(codel: &str) -> Result<CoDel, String> {
+        match parse_params(codel, "CoDel")?[..] {
+            [target, interval] if target > 0.0 && interval > 0.0 => Ok(CoDel::create(
+                Time::from_secs(target),
+                Time::from_secs(interval),
+            )),
+            _ => Err(format!(
+                "Expected TARGET,INTERVAL, strictly positive, found {}",
+                codel
+            )),
+        }
+    }
+
+    fn control_law(&self, time: Time) -> Time {
+        time + Time((self.interval.0 as f64 / (self.count as f64).sqrt()) as u64)
+    }
+
+    /// Decides whether to drop a packet leaving the queue at `now` after waiting for `sojourn`
+    fn drops(&mut self, sojourn: Time, now: Time) -> bool {
+        let ok_to_drop = if sojourn < self.target {
+            self.first_above = None;
+            false
+        } else {
+            match self.first_above {
+                None => {
+                    self.first_above = Some(now + self.interval);
+                    false
+                }
+                Some(first_above) => now >= first_above,
+            }
+        };
+
+        if self.dropping {
+            if !ok_to_drop {
+                self.dropping = false;
+                return false;
+            }
+            if now < self.drop_next {
+                return false;
+            }
+            self.count += 1;
+            self.drop_next = self.control_law(self.drop_next);
+            return true;
+        }
+
+        if !ok_to_drop {
+            return false;
+        }
+
+        self.dropping = true;
+        // Go back to the previous drop rate if the last dropping state ended recently
+        let delta = self.count - self.last_count;
+        self.count = if delta > 1 && now.0 < self.drop_next.0 + 16 * self.interval.0 {
+            delta
+        } else {
+            1
+        };
+        self.last_count = self.count;
+        self.drop_next = self.control_law(now);
+
+        true
+    }
+}
+
+/// Active queue management discipline
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Aqm {
+    #[default]
+    DropTail,
+    Red(Red),
+    CoDel(CoDel),
+}
+
+/// First in, first out output queue in front of one direction of a link
+#[derive(Clone, Debug)]
+pub struct Queue {
+    limit: Option<QueueLimit>,
+    aqm: Aqm,
+    backlog: VecDeque<(Time, u32)>, // When each queued packet leaves, and its size
+
+    delays: Vec<Time>, // Time spent waiting by every transmitted packet
+    arrivals: u64,
+    occupancy: u64, // Sum of the packets found by every arrival
+    max_packets: u64,
+    max_bytes: u64,
+    tail_drops: u64,
+    aqm_drops: u64,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Queue::create(None, Aqm::DropTail)
+    }
+}
+
+impl Queue {
+    /// Unlimited drop-tail queues behave as if each terminal had one of its own
+    pub fn create(limit: Option<QueueLimit>, aqm: Aqm) -> Queue {
+        Queue {
+            limit,
+            aqm,
+            backlog: VecDeque::new(),
+
+            delays: Vec::new(),
+            arrivals: 0,
+            occupancy: 0,
+            max_packets: 0,
+            max_bytes: 0,
+            tail_drops: 0,
+            aqm_drops: 0,
+        }
+    }
+
+    /// Queues a packet of `bytes` that takes `tx` to transmit once the medium gets free at
+    /// `free_at`. Returns when the transmission ends, unless the packet gets dropped.
+    pub fn enqueue(
+        &mut self,
+        bytes: u32,
+        tx: Time,
+        now: Time,
+        free_at: Time,
+        rng: &mut dyn RngCore,
+    ) -> Option<Time> {
+        while self
+            .backlog
+            .front()
+            .is_some_and(|&(leaves, _)| leaves <= now)
+        {
+            self.backlog.pop_front();
+        }
+        let packets = self.backlog.len() as u64;
+        let queued_bytes = self
+            .backlog
+            .iter()
+            .map(|&(_, bytes)| u64::from(bytes))
+            .sum::<u64>();
+
+        self.arrivals += 1;
+        self.occupancy += packets;
+
+        let full = match self.limit {
+            Some(QueueLimit::Packets(limit)) => packets >= limit,
+            Some(QueueLimit::Bytes(limit)) => queued_bytes + u64::from(bytes) > limit,
+            None => false,
+        };
+        if full {
+            self.tail_drops += 1;
+            return None;
+        }
+
+        if let Aqm::Red(red) = &mut self.aqm {
+            let idle_slots = match tx.0 {
+                0 => 0.0,
+                tx => now.0.saturating_sub(free_at.0) as f64 / tx as f64,
+            };
+            if red.drops(packets, idle_slots, rng) {
+                self.aqm_drops += 1;
+                return None;
+            }
+        }
+
+        let start = max(now, free_at);
+        if let Aqm::CoDel(codel) = &mut self.aqm {
+            if codel.drops(Time(start.0 - now.0), start) {
+                // It stays in the queue until CoDel gets to it
+                self.aqm_drops += 1;
+                self.backlog.push_back((start, bytes));
+                return None;
+            }
+        }
+
+        let end = start + tx;
+        self.backlog.push_back((end, bytes));
+        self.delays.push(Time(start.0 - now.0));
+        self.max_packets = max(self.max_packets, packets + 1);
+        self.max_bytes = max(self.max_bytes, queued_bytes + u64::from(bytes));
+
+        Some(end)
+    }
+
+    /// Tells whether it differs from an unlimited drop-tail queue
+    pub fn is_managed(&self) -> bool {
+        self.limit.is_some() || self.aqm != Aqm::DropTail
+    }
+
+    fn delay_percentile(delays: &[Time], percentile: usize) -> Time {
+        delays[(delays.len() - 1) * percentile / 100]
+    }
+
+    pub fn show_stats(&self, name: &str) {
+        if self.arrivals == 0 {
+            return;
+        }
+
+        print!(
+            "{} queue: {} arrivals, {} dropped at the tail",
+            name, self.arrivals, self.tail_drops
+        );
+        match self.aqm {
+            Aqm::DropTail => println!(),
+            Aqm::Red(_) => println!(", {} dropped by RED", self.aqm_drops),
+            Aqm::CoDel(_) => println!(", {} dropped by CoDel", self.aqm_drops),
+        }
+        println!(
+            "{} queue occupancy: {} packets on average at arrivals, up to {} packets ({} bytes)",
+            name,
+            self.occupancy as f64 / self.arrivals as f64,
+            self.max_packets,
+            self.max_bytes
+        );
+
+        let mut delays = self.delays.clone();
+        if delays.is_empty() {
+            return;
+        }
+        delays.sort();
+        println!(
+            "{} queueing delay: {} s median, {} s 90th percentile, {} s 99th percentile, {} s max",
+            name,
+            Queue::delay_percentile(&delays, 50).as_secs(),
+            Queue::delay_percentile(&delays, 90).as_secs(),
+            Queue::delay_percentile(&delays, 99).as_secs(),
+            Queue::delay_percentile(&delays, 100).as_secs()
+        );
+    }
+}
@@ -19,21 +19,128 @@ use std::fmt;
 
 use super::TerminalAddress;
 
+pub const MAX_SACK_BLOCKS: usize = 4;
+
+/// Range of sequence numbers, both ends included, that arrived out of order
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct SackBlock {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct SackBlocks {
+    blocks: [SackBlock; MAX_SACK_BLOCKS],
+    len: usize,
+}
+
+impl SackBlocks {
+    /// Appends a block, unless all the room is already taken
+    pub fn push(&mut self, block: SackBlock) -> bool {
+        if self.len == MAX_SACK_BLOCKS {
+            return false;
+        }
+
+        self.blocks[self.len] = block;
+        self.len += 1;
+
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SackBlock> {
+        self.blocks[..self.len].iter()
+    }
+}
+
+/// What a packet is for, regardless of its length
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum PacketKind {
+    Data,
+    Ack,
+    Nak,
+    Syn,
+    Fin,
+}
+
+impl PacketKind {
+    pub const ALL: [PacketKind; 5] = [
+        PacketKind::Data,
+        PacketKind::Ack,
+        PacketKind::Nak,
+        PacketKind::Syn,
+        PacketKind::Fin,
+    ];
+}
+
+impl fmt::Display for PacketKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PacketKind::Data => "DATA",
+            PacketKind::Ack => "ACK",
+            PacketKind::Nak => "NAK",
+            PacketKind::Syn => "SYN",
+            PacketKind::Fin => "FIN",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Acknowledgement carried by a data packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct Ack {
+    pub seqno: u64,     // As carried on the wire
+    pub abs_seqno: u64, // Only known to the simulator
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct Packet {
-    pub seqno: u64,
+    pub kind: PacketKind,
+    pub seqno: u64,     // As carried on the wire
+    pub abs_seqno: u64, // Only known to the simulator, to detect protocol failures
     pub header_size: u32,
     pub payload_size: u32,
     pub src_addr: TerminalAddress,
     pub dst_addr: TerminalAddress,
+    pub hop_src: TerminalAddress, // Node that put it on the current link
+    pub ack: Option<Ack>,         // Piggybacked on data
+    pub sack: SackBlocks,
+    pub corrupted: bool, // Only known to the simulator, set when the checksum misses errors
 }
 
 impl fmt::Display for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "seqno: {}, H: {}, P: {} ({} -> {})",
-            self.seqno, self.header_size, self.payload_size, self.src_addr, self.dst_addr
-        )
+            "{} seqno: {}, H: {}, P: {} ({} -> {})",
+            self.kind,
+            self.seqno,
+            self.header_size,
+            self.payload_size,
+            self.src_addr,
+            self.dst_addr
+        )?;
+
+        if let Some(ack) = self.ack {
+            write!(f, " ACK {}", ack.seqno)?;
+        }
+
+        for block in self.sack.iter() {
+            write!(f, " SACK [{}, {}]", block.start, block.end)?;
+        }
+
+        if self.corrupted {
+            write!(f, " (corrupted)")?;
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,106 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::link::{AttachedLink, LinkAddress};
+use super::packet::Packet;
+use super::{Event, Target, TerminalAddress};
+use crate::simulator::Payload;
+use eee_hyst::Time;
+use log::{debug, info};
+use rand::RngCore;
+use std::collections::BTreeMap;
+
+/// Store-and-forward node that passes packets on following a static routing table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relay {
+    pub addr: TerminalAddress,
+    routes: BTreeMap<TerminalAddress, LinkAddress>, // Outgoing link for every destination
+
+    forwarded: u64,
+    dropped: u64, // By the queue of the outgoing link
+}
+
+impl Relay {
+    pub fn create(addr: TerminalAddress) -> Relay {
+        Relay {
+            addr,
+            routes: BTreeMap::new(),
+
+            forwarded: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn add_route(&mut self, dst_addr: TerminalAddress, link_addr: LinkAddress) {
+        self.routes.insert(dst_addr, link_addr);
+    }
+
+    /// Link to forward a packet for `dst_addr` through
+    pub fn route(&self, dst_addr: TerminalAddress) -> LinkAddress {
+        match self.routes.get(&dst_addr) {
+            Some(&link_addr) => link_addr,
+            None => panic!("Relay {} has no route to {}", self.addr, dst_addr),
+        }
+    }
+
+    /// Queues the packet in `event` for transmission over `link`, which must be its route
+    pub fn process(
+        &mut self,
+        event: Event,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let packet = match event.kind {
+            Payload(packet) => packet,
+            _ => panic!("Relay event with no packet to forward"),
+        };
+        info!(
+            "{} relay {} forwarding {}",
+            now.as_secs(),
+            self.addr,
+            packet
+        );
+
+        let packet = Packet {
+            hop_src: self.addr,
+            ..packet
+        };
+        match link.enqueue(packet, now, rng) {
+            Some(delivery_time) => {
+                self.forwarded += 1;
+                vec![Event {
+                    due_time: delivery_time,
+                    target: Target::Link(self.route(packet.dst_addr)),
+                    kind: Payload(packet),
+                }]
+            }
+            None => {
+                debug!("{} relay {} dropped {}", now.as_secs(), self.addr, packet);
+                self.dropped += 1;
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn show_stats(&self) {
+        println!(
+            "Relay {} forwarded {} packets, {} dropped by the queues",
+            self.addr, self.forwarded, self.dropped
+        );
+    }
+}
@@ -0,0 +1,961 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    AckMode, AckPolicy, Aqm, BitErrors, BitFlips, ChannelTrace, Checksum, CoDel, ErrorModel,
+    GilbertElliott, Jitter, Link, LinkAddress, LossPattern, LossScript, LossTrace, Network,
+    PacketErrors, Protocol, Queue, QueueLimit, Red, Relay, Terminal, TerminalAddress,
+};
+use clap::ValueEnum;
+use eee_hyst::Time;
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// How long to simulate, in seconds, and when to stop earlier
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SimulationSpec {
+    duration: f64,
+    seed: Option<u64>,
+    delivered: Option<u64>, // Packets every flow has to deliver to stop
+}
+
+fn default_header() -> u32 {
+    40
+}
+
+fn default_payload() -> u32 {
+    1460
+}
+
+fn default_window() -> u16 {
+    1
+}
+
+fn default_sack_block_size() -> u32 {
+    8
+}
+
+/// Terminal with the same parameters as the command line options, times in seconds
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TerminalSpec {
+    name: String,
+    #[serde(default = "default_header")]
+    header: u32,
+    #[serde(default = "default_payload")]
+    payload: u32,
+    #[serde(default = "default_window")]
+    window: u16,
+    protocol: Option<String>,
+    seq_bits: Option<u32>,
+    ack: Option<String>,
+    ack_every: Option<u64>,
+    ack_delay: Option<f64>,
+    #[serde(default)]
+    ack_defer_out_of_order: bool,
+    #[serde(default)]
+    nak: bool,
+    sack: Option<u8>,
+    #[serde(default = "default_sack_block_size")]
+    sack_block_size: u32,
+    rto: Option<f64>,
+    min_rto: Option<f64>,
+}
+
+impl TerminalSpec {
+    fn terminal(&self) -> Result<Terminal, String> {
+        let protocol = match &self.protocol {
+            Some(protocol) => Protocol::from_str(protocol, true)
+                .map_err(|_| format!("unknown protocol {}", protocol))?,
+            None => Protocol::default(),
+        };
+        if self.window == 0 {
+            return Err("the window has to hold at least one packet".to_string());
+        }
+        if protocol.is_stop_and_wait() && self.window != 1 {
+            return Err("Stop-and-Wait protocols require a window of one packet".to_string());
+        }
+        let terminal =
+            Terminal::create(self.header, self.payload, self.window).with_protocol(protocol);
+
+        let terminal = match self.seq_bits {
+            Some(seq_bits) if seq_bits < 64 => terminal.with_seq_bits(seq_bits),
+            Some(seq_bits) => {
+                return Err(format!("{} sequence number bits are too many", seq_bits))
+            }
+            None => terminal,
+        };
+
+        if let Some(max_window) = terminal.max_window() {
+            if u64::from(self.window) > max_window {
+                println!(
+                    "Terminal {}: window size {} exceeds the maximum of {} for {:?} with these sequence numbers, expect failures",
+                    self.name, self.window, max_window, protocol
+                );
+            }
+        }
+
+        let terminal = match &self.ack {
+            Some(ack) => terminal.with_ack_mode(
+                AckMode::from_str(ack, true).map_err(|_| format!("unknown ACK mode {}", ack))?,
+            ),
+            None => terminal,
+        };
+
+        if self.ack_delay.is_some_and(|delay| delay <= 0.0) {
+            return Err("ACK delay has to be strictly positive".to_string());
+        }
+        if self.ack_every == Some(0) {
+            return Err("ACKs have to be sent once every one packet at least".to_string());
+        }
+        let ack_delay = self.ack_delay.map(Time::from_secs);
+        let terminal = terminal.with_ack_policy(AckPolicy {
+            every: self
+                .ack_every
+                .unwrap_or(if ack_delay.is_some() { u64::MAX } else { 1 }),
+            delay: ack_delay,
+            immediate_out_of_order: !self.ack_defer_out_of_order,
+        });
+
+        let terminal = if self.nak {
+            terminal.with_naks()
+        } else {
+            terminal
+        };
+
+        let terminal = match self.sack {
+            Some(_) if !protocol.buffers_out_of_order() => {
+                return Err(
+                    "SACK blocks require a receiver that buffers out-of-order packets (sr)"
+                        .to_string(),
+                )
+            }
+            Some(sack @ 1..=4) => terminal.with_sack(sack.into(), self.sack_block_size),
+            Some(sack) => return Err(format!("{} SACK blocks are not between 1 and 4", sack)),
+            None => terminal,
+        };
+
+        let terminal = match self.rto {
+            Some(rto) if rto > 0.0 => terminal.with_adaptive_rto(Time::from_secs(rto)),
+            Some(_) => return Err("initial RTO has to be strictly positive".to_string()),
+            None => terminal,
+        };
+
+        let terminal = match self.min_rto {
+            Some(_) if self.rto.is_none() => {
+                return Err("the minimum RTO requires an adaptive RTO".to_string())
+            }
+            Some(min_rto) if min_rto >= 0.0 => terminal.with_min_rto(Time::from_secs(min_rto)),
+            Some(_) => return Err("minimum RTO has to be positive".to_string()),
+            None => terminal,
+        };
+
+        if terminal.defers_individual_acks() {
+            return Err("only cumulative ACKs can be deferred".to_string());
+        }
+
+        Ok(terminal)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RelaySpec {
+    name: String,
+}
+
+/// Error model of one direction of a link, with the same parameters as the command line
+/// options
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+enum ErrorSpec {
+    #[default]
+    None,
+    Ber {
+        ber: f64,
+    },
+    Burst {
+        ber: f64,
+        burst_ber: f64,
+        burst_length: f64,
+        burst_interval: f64,
+    },
+    Per {
+        per: f64,
+    },
+    Pattern {
+        pattern: String,
+    },
+    Checksum {
+        ber: f64,
+        checksum: String,
+    },
+    Trace {
+        file: String,
+    },
+}
+
+/// Contents of the `what` file at `path`, relative to the working directory
+fn read(path: &str, what: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("could not read the {} {}: {}", what, path, e))
+}
+
+fn probability(value: f64, name: &str) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} has to be between 0 and 1", name))
+    }
+}
+
+impl ErrorSpec {
+    fn check(&self) -> Result<(), String> {
+        match self {
+            ErrorSpec::None | ErrorSpec::Pattern { .. } | ErrorSpec::Trace { .. } => Ok(()),
+            ErrorSpec::Ber { ber } | ErrorSpec::Checksum { ber, .. } => {
+                probability(*ber, "BER").map(|_| ())
+            }
+            ErrorSpec::Burst {
+                ber,
+                burst_ber,
+                burst_length,
+                burst_interval,
+            } => {
+                probability(*ber, "BER")?;
+                probability(*burst_ber, "Burst BER")?;
+                if *burst_length > 0.0 && *burst_interval > 0.0 {
+                    Ok(())
+                } else {
+                    Err("burst length and interval have to be strictly positive".to_string())
+                }
+            }
+            ErrorSpec::Per { per } => probability(*per, "PER").map(|_| ()),
+        }
+    }
+
+    fn checksum(checksum: &str) -> Result<Checksum, String> {
+        Checksum::from_str(checksum, true).map_err(|_| format!("unknown checksum {}", checksum))
+    }
+
+    fn pattern(pattern: &str) -> Result<LossPattern, String> {
+        LossPattern::parse(pattern).ok_or_else(|| format!("invalid loss pattern {}", pattern))
+    }
+
+    fn model(&self) -> Result<Box<dyn ErrorModel>, String> {
+        self.check()?;
+
+        Ok(match self {
+            ErrorSpec::None => Box::new(BitErrors::create(0.0)),
+            ErrorSpec::Ber { ber } => Box::new(BitErrors::create(*ber)),
+            ErrorSpec::Burst {
+                ber,
+                burst_ber,
+                burst_length,
+                burst_interval,
+            } => Box::new(GilbertElliott::create(
+                *ber,
+                *burst_ber,
+                *burst_interval,
+                *burst_length,
+            )),
+            ErrorSpec::Per { per } => Box::new(PacketErrors::create(*per)),
+            ErrorSpec::Pattern { pattern } => Box::new(ErrorSpec::pattern(pattern)?),
+            ErrorSpec::Checksum { ber, checksum } => {
+                Box::new(BitFlips::create(*ber, ErrorSpec::checksum(checksum)?))
+            }
+            ErrorSpec::Trace { file } => Box::new(
+                LossTrace::parse(&read(file, "loss trace")?)
+                    .ok_or_else(|| format!("invalid loss trace {}", file))?,
+            ),
+        })
+    }
+}
+
+/// Link between two nodes, with the same parameters as the command line options, times in
+/// seconds
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LinkSpec {
+    from: String,
+    to: String,
+    capacity: f64,
+    #[serde(default)]
+    delay: f64,
+    #[serde(default)]
+    errors: ErrorSpec,
+    reverse_capacity: Option<f64>,
+    reverse_delay: Option<f64>,
+    reverse_errors: Option<ErrorSpec>,
+    jitter: Option<String>,
+    #[serde(default)]
+    preserve_order: bool,
+    duplicate: Option<f64>,
+    checksum_miss: Option<f64>,
+    drop: Option<String>,
+    drop_script: Option<String>,
+    channel_trace: Option<String>,
+    queue: Option<u64>,
+    queue_bytes: Option<u64>,
+    red: Option<String>,
+    codel: Option<String>,
+    #[serde(default)]
+    half_duplex: bool,
+}
+
+impl LinkSpec {
+    fn link(&self) -> Result<Link, String> {
+        if self.capacity <= 0.0
+            || self
+                .reverse_capacity
+                .is_some_and(|capacity| capacity <= 0.0)
+        {
+            return Err("capacity has to be strictly positive".to_string());
+        }
+        if self.delay < 0.0 || self.reverse_delay.is_some_and(|delay| delay < 0.0) {
+            return Err("propagation delay has to be positive".to_string());
+        }
+        let delay = Time::from_secs(self.delay);
+        let link = match &self.channel_trace {
+            Some(_) if !matches!(self.errors, ErrorSpec::None) => {
+                return Err("a channel trace replaces the error model".to_string())
+            }
+            Some(file) => {
+                let trace = ChannelTrace::parse(&read(file, "channel trace")?)
+                    .map_err(|e| format!("invalid channel trace {}: {}", file, e))?;
+                Link::create(self.capacity, delay, trace.losses()).with_delay_trace(trace.delays())
+            }
+            None => Link::create(self.capacity, delay, self.errors.model()?),
+        };
+
+        let link = if self.reverse_capacity.is_some() || self.reverse_delay.is_some() {
+            link.with_reverse(
+                self.reverse_capacity.unwrap_or(self.capacity),
+                self.reverse_delay.map_or(delay, Time::from_secs),
+            )
+        } else {
+            link
+        };
+
+        let link = match &self.reverse_errors {
+            Some(errors) => link.with_reverse_error_model(errors.model()?),
+            None => link,
+        };
+
+        let link = match &self.jitter {
+            Some(jitter) => link.with_jitter(Jitter::parse(jitter)?),
+            None => link,
+        };
+
+        let link = if self.preserve_order {
+            link.with_preserved_order()
+        } else {
+            link
+        };
+
+        let link = match self.duplicate {
+            Some(duplication) => {
+                link.with_duplication(probability(duplication, "Duplication probability")?)
+            }
+            None => link,
+        };
+
+        let link = match self.checksum_miss {
+            Some(miss) => link.with_checksum_miss(probability(miss, "Checksum miss probability")?),
+            None => link,
+        };
+
+        let link = match (&self.drop, &self.drop_script) {
+            (Some(_), Some(_)) => {
+                return Err("the dropped packets go either in drop or in drop_script".to_string())
+            }
+            (Some(rules), None) => link.with_loss_script(LossScript::parse(rules)?),
+            (None, Some(file)) => {
+                link.with_loss_script(LossScript::parse(&read(file, "loss script")?)?)
+            }
+            (None, None) => link,
+        };
+
+        let limit = match (self.queue, self.queue_bytes) {
+            (Some(_), Some(_)) => {
+                return Err("the queue size goes either in packets or in bytes".to_string())
+            }
+            (Some(packets), None) => Some(QueueLimit::Packets(packets)),
+            (None, Some(bytes)) => Some(QueueLimit::Bytes(bytes)),
+            (None, None) => None,
+        };
+        let aqm = match (&self.red, &self.codel) {
+            (Some(_), Some(_)) => return Err("RED and CoDel cannot be used together".to_string()),
+            (Some(red), None) => Aqm::Red(Red::parse(red)?),
+            (None, Some(codel)) => Aqm::CoDel(CoDel::parse(codel)?),
+            (None, None) => Aqm::DropTail,
+        };
+        let link = link.with_queue(Queue::create(limit, aqm));
+
+        Ok(if self.half_duplex {
+            link.with_half_duplex()
+        } else {
+            link
+        })
+    }
+}
+
+/// Data sent from one terminal to another, starting at `start` seconds
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlowSpec {
+    from: String,
+    to: String,
+    #[serde(default)]
+    start: f64,
+}
+
+/// Terminals at both ends of a flow built from a scenario
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flow {
+    pub src_addr: TerminalAddress,
+    pub dst_addr: TerminalAddress,
+    pub start: Time,
+}
+
+/// Nodes and links along the way from a terminal to another, by their position in the
+/// scenario
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Route {
+    nodes: Vec<usize>,
+    links: Vec<usize>,
+}
+
+/// Network, traffic and stop conditions of a simulation, as written in a TOML or JSON file.
+/// Nodes are either terminals, attached to a single link and taking part in a single flow, or
+/// relays, that forward packets along the shortest path to their destination.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    simulation: SimulationSpec,
+    terminals: Vec<TerminalSpec>,
+    #[serde(default)]
+    relays: Vec<RelaySpec>,
+    links: Vec<LinkSpec>,
+    flows: Vec<FlowSpec>,
+}
+
+impl Scenario {
+    /// Reads a scenario file, in JSON if its name ends in .json and in TOML otherwise
+    pub fn load(path: &str) -> Result<Scenario, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the scenario {}: {}", path, e))?;
+
+        let scenario = if Path::new(path).extension().is_some_and(|ext| ext == "json") {
+            Scenario::parse_json(&text)
+        } else {
+            Scenario::parse_toml(&text)
+        };
+
+        scenario.map_err(|e| format!("Invalid scenario {}: {}", path, e))
+    }
+
+    pub fn parse_toml(scenario: &str) -> Result<Scenario, String> {
+        toml::from_str(scenario).map_err(|e| e.to_string())
+    }
+
+    pub fn parse_json(scenario: &str) -> Result<Scenario, String> {
+        serde_json::from_str(scenario).map_err(|e| e.to_string())
+    }
+
+    pub fn duration(&self) -> Time {
+        Time::from_secs(self.simulation.duration)
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.simulation.seed
+    }
+
+    /// Packets every flow has to deliver for the simulation to stop before its duration
+    pub fn delivered(&self) -> Option<u64> {
+        self.simulation.delivered
+    }
+
+    /// Name of the node at `addr` in the network built from the scenario
+    pub fn node_name(&self, addr: TerminalAddress) -> &str {
+        let n = usize::from(addr);
+        match self.terminals.get(n) {
+            Some(terminal) => &terminal.name,
+            None => &self.relays[n - self.terminals.len()].name,
+        }
+    }
+
+    /// Shortest path from `src` to `dst` over `ends`, the nodes at both sides of every link
+    fn route(&self, src: usize, dst: usize, ends: &[(usize, usize)]) -> Option<Route> {
+        let mut previous = BTreeMap::new(); // Node and link every node is reached from
+        let mut pending = VecDeque::from([src]);
+
+        while let Some(node) = pending.pop_front() {
+            if node == dst {
+                let mut route = Route {
+                    nodes: vec![dst],
+                    links: Vec::new(),
+                };
+                let mut node = dst;
+                while let Some(&(from, link)) = previous.get(&node) {
+                    route.nodes.insert(0, from);
+                    route.links.insert(0, link);
+                    node = from;
+                }
+
+                return Some(route);
+            }
+            if node != src && node < self.terminals.len() {
+                // Terminals do not forward packets
+                continue;
+            }
+
+            for (link, &(from, to)) in ends.iter().enumerate() {
+                let next = match node {
+                    node if node == from => to,
+                    node if node == to => from,
+                    _ => continue,
+                };
+                if next != src && !previous.contains_key(&next) {
+                    previous.insert(next, (node, link));
+                    pending.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Checks the scenario and builds its network, with terminals first and relays next, and
+    /// every node and link in the order they are written
+    pub fn build(&self) -> Result<(Network, Vec<Flow>), String> {
+        if self.simulation.duration <= 0.0 {
+            return Err("Simulation duration has to be strictly positive".to_string());
+        }
+        if self.flows.is_empty() {
+            return Err("There has to be at least one flow".to_string());
+        }
+
+        let mut nodes = BTreeMap::new();
+        for (n, name) in self
+            .terminals
+            .iter()
+            .map(|terminal| &terminal.name)
+            .chain(self.relays.iter().map(|relay| &relay.name))
+            .enumerate()
+        {
+            if nodes.insert(name.as_str(), n).is_some() {
+                return Err(format!("Node {} is declared more than once", name));
+            }
+        }
+        let node = |name: &str, context: &str| {
+            nodes
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("{}: unknown node {}", context, name))
+        };
+
+        let terminal_count = self.terminals.len();
+        let mut ends = Vec::with_capacity(self.links.len());
+        let mut attached = vec![Vec::new(); terminal_count]; // Links of every terminal
+        for (n, link) in self.links.iter().enumerate() {
+            let context = format!("Link {} ({} -> {})", n + 1, link.from, link.to);
+            let (from, to) = (node(&link.from, &context)?, node(&link.to, &context)?);
+            if from == to {
+                return Err(format!("{}: both ends are the same node", context));
+            }
+
+            for end in [from, to] {
+                if end < terminal_count {
+                    attached[end].push(n);
+                }
+            }
+            ends.push((from, to));
+        }
+        for (terminal, links) in self.terminals.iter().zip(&attached) {
+            if links.len() != 1 {
+                return Err(format!(
+                    "Terminal {} has to be attached to exactly one link, not {}",
+                    terminal.name,
+                    links.len()
+                ));
+            }
+        }
+
+        let mut peers = vec![None; terminal_count];
+        let mut routes = Vec::with_capacity(self.flows.len());
+        for flow in &self.flows {
+            let context = format!("Flow {} -> {}", flow.from, flow.to);
+            let (src, dst) = (node(&flow.from, &context)?, node(&flow.to, &context)?);
+            if src >= terminal_count || dst >= terminal_count || src == dst {
+                return Err(format!("{}: has to go between two terminals", context));
+            }
+            if flow.start < 0.0 {
+                return Err(format!("{}: start time has to be positive", context));
+            }
+
+            for (end, peer) in [(src, dst), (dst, src)] {
+                if peers[end].replace(peer).is_some() {
+                    return Err(format!(
+                        "{}: terminal {} already takes part in another flow",
+                        context, self.terminals[end].name
+                    ));
+                }
+            }
+            routes.push(
+                self.route(src, dst, &ends)
+                    .ok_or_else(|| format!("{}: no path between both terminals", context))?,
+            );
+        }
+        let peers = peers
+            .into_iter()
+            .zip(&self.terminals)
+            .map(|(peer, terminal)| {
+                peer.ok_or_else(|| format!("Terminal {} is not part of any flow", terminal.name))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        let mut network = Network::default();
+        for (n, terminal) in self.terminals.iter().enumerate() {
+            let attached_terminal = terminal
+                .terminal()
+                .map_err(|e| format!("Terminal {}: {}", terminal.name, e))?
+                .attach_to_link(
+                    TerminalAddress::create(n),
+                    LinkAddress::create(attached[n][0]),
+                    TerminalAddress::create(peers[n]),
+                );
+            network.add_terminal(attached_terminal);
+        }
+
+        let mut relays = (terminal_count..terminal_count + self.relays.len())
+            .map(|n| Relay::create(TerminalAddress::create(n)))
+            .collect::<Vec<Relay>>();
+        for route in &routes {
+            let (src, dst) = (route.nodes[0], route.nodes[route.nodes.len() - 1]);
+            for (hop, &node) in route.nodes.iter().enumerate().skip(1) {
+                if node >= terminal_count {
+                    let relay = &mut relays[node - terminal_count];
+                    relay.add_route(
+                        TerminalAddress::create(src),
+                        LinkAddress::create(route.links[hop - 1]),
+                    );
+                    relay.add_route(
+                        TerminalAddress::create(dst),
+                        LinkAddress::create(route.links[hop]),
+                    );
+                }
+            }
+        }
+        for relay in relays {
+            network.add_relay(relay);
+        }
+
+        for (n, (link, &(from, to))) in self.links.iter().zip(&ends).enumerate() {
+            let attached_link = link
+                .link()
+                .map_err(|e| format!("Link {} ({} -> {}): {}", n + 1, link.from, link.to, e))?
+                .attach_terminals(TerminalAddress::create(from), TerminalAddress::create(to));
+            network.add_link(attached_link);
+        }
+
+        for route in &routes {
+            network.add_path_delays(
+                &route
+                    .nodes
+                    .iter()
+                    .map(|&n| TerminalAddress::create(n))
+                    .collect::<Vec<TerminalAddress>>(),
+                &route
+                    .links
+                    .iter()
+                    .map(|&n| LinkAddress::create(n))
+                    .collect::<Vec<LinkAddress>>(),
+            );
+        }
+
+        let flows = self
+            .flows
+            .iter()
+            .zip(&routes)
+            .map(|(flow, route)| Flow {
+                src_addr: TerminalAddress::create(route.nodes[0]),
+                dst_addr: TerminalAddress::create(route.nodes[route.nodes.len() - 1]),
+                start: Time::from_secs(flow.start),
+            })
+            .collect();
+
+        Ok((network, flows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::Simulator;
+
+    /// Two terminals on both ends of a link, sending from the first to the second
+    const DIRECT: &str = r#"
+        [simulation]
+        duration = 1.0
+
+        [[terminals]]
+        name = "a"
+
+        [[terminals]]
+        name = "b"
+
+        [[links]]
+        from = "a"
+        to = "b"
+        capacity = 1e6
+
+        [[flows]]
+        from = "a"
+        to = "b"
+    "#;
+
+    fn build(scenario: &str) -> Result<(Network, Vec<Flow>), String> {
+        Scenario::parse_toml(scenario)?.build()
+    }
+
+    fn build_error(scenario: &str) -> String {
+        match build(scenario) {
+            Ok(_) => panic!("The scenario should not build"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn builds_the_sample_scenario() {
+        let scenario = Scenario::parse_toml(include_str!("../../../scenarios/two-flows.toml"))
+            .expect("The sample scenario should parse");
+        let (network, flows) = scenario.build().expect("The sample scenario should build");
+
+        assert_eq!(network.links().count(), 5);
+        assert_eq!(network.relays().count(), 2);
+        assert_eq!(flows.len(), 2);
+        assert_eq!(scenario.node_name(flows[0].src_addr), "a1");
+        assert_eq!(scenario.node_name(flows[0].dst_addr), "b1");
+        assert_eq!(scenario.node_name(flows[1].src_addr), "a2");
+        assert_eq!(scenario.node_name(flows[1].dst_addr), "b2");
+        assert_eq!(flows[1].start, Time::from_secs(0.1));
+        assert_eq!(scenario.delivered(), Some(2000));
+    }
+
+    #[test]
+    fn builds_a_direct_link() {
+        let (network, flows) = build(DIRECT).expect("The scenario should build");
+
+        assert_eq!(network.links().count(), 1);
+        assert_eq!(network.relays().count(), 0);
+        assert_eq!(flows.len(), 1);
+    }
+
+    #[test]
+    fn rejects_duplicate_nodes() {
+        let scenario = DIRECT.replace(r#"name = "b""#, r#"name = "a""#);
+
+        assert_eq!(build_error(&scenario), "Node a is declared more than once");
+    }
+
+    #[test]
+    fn rejects_terminals_on_several_links() {
+        let scenario = format!(
+            r#"{}
+            [[links]]
+            from = "b"
+            to = "a"
+            capacity = 1e6
+            "#,
+            DIRECT
+        );
+
+        assert_eq!(
+            build_error(&scenario),
+            "Terminal a has to be attached to exactly one link, not 2"
+        );
+    }
+
+    #[test]
+    fn rejects_terminals_without_links() {
+        let scenario = format!(
+            r#"{}
+            [[terminals]]
+            name = "c"
+            "#,
+            DIRECT
+        );
+
+        assert_eq!(
+            build_error(&scenario),
+            "Terminal c has to be attached to exactly one link, not 0"
+        );
+    }
+
+    #[test]
+    fn rejects_flows_without_a_path() {
+        let scenario = r#"
+            [simulation]
+            duration = 1.0
+
+            [[terminals]]
+            name = "a"
+
+            [[terminals]]
+            name = "b"
+
+            [[relays]]
+            name = "r1"
+
+            [[relays]]
+            name = "r2"
+
+            [[links]]
+            from = "a"
+            to = "r1"
+            capacity = 1e6
+
+            [[links]]
+            from = "r2"
+            to = "b"
+            capacity = 1e6
+
+            [[flows]]
+            from = "a"
+            to = "b"
+        "#;
+
+        assert_eq!(
+            build_error(scenario),
+            "Flow a -> b: no path between both terminals"
+        );
+    }
+
+    #[test]
+    fn rejects_terminals_in_two_flows() {
+        let scenario = r#"
+            [simulation]
+            duration = 1.0
+
+            [[terminals]]
+            name = "a"
+
+            [[terminals]]
+            name = "b"
+
+            [[terminals]]
+            name = "c"
+
+            [[relays]]
+            name = "r"
+
+            [[links]]
+            from = "a"
+            to = "r"
+            capacity = 1e6
+
+            [[links]]
+            from = "r"
+            to = "b"
+            capacity = 1e6
+
+            [[links]]
+            from = "r"
+            to = "c"
+            capacity = 1e6
+
+            [[flows]]
+            from = "a"
+            to = "b"
+
+            [[flows]]
+            from = "a"
+            to = "c"
+        "#;
+
+        assert_eq!(
+            build_error(scenario),
+            "Flow a -> c: terminal a already takes part in another flow"
+        );
+    }
+
+    #[test]
+    fn rejects_red_with_codel() {
+        let scenario = DIRECT.replace(
+            "capacity = 1e6",
+            "capacity = 1e6\nred = \"16,48\"\ncodel = \"0.005,0.1\"",
+        );
+
+        assert_eq!(
+            build_error(&scenario),
+            "Link 1 (a -> b): RED and CoDel cannot be used together"
+        );
+    }
+
+    #[test]
+    fn rejects_channel_traces_with_an_error_model() {
+        let scenario = DIRECT.replace(
+            "capacity = 1e6",
+            "capacity = 1e6\nchannel_trace = \"trace.csv\"\nerrors = { model = \"per\", per = 0.1 }",
+        );
+
+        assert_eq!(
+            build_error(&scenario),
+            "Link 1 (a -> b): a channel trace replaces the error model"
+        );
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let scenario = DIRECT.replace(
+            "capacity = 1e6",
+            "capacity = 1e6\nerrors = { model = \"trace\", file = \"/nonexistent\" }",
+        );
+
+        assert!(build_error(&scenario)
+            .starts_with("Link 1 (a -> b): could not read the loss trace /nonexistent"));
+    }
+
+    #[test]
+    fn runs_stop_and_wait() {
+        let scenario = DIRECT
+            .replace(r#"name = "a""#, "name = \"a\"\nprotocol = \"saw\"")
+            .replace(r#"name = "b""#, "name = \"b\"\nprotocol = \"saw\"");
+        let scenario = Scenario::parse_toml(&scenario).expect("The scenario should parse");
+        let (mut network, flows) = scenario.build().expect("The scenario should build");
+        let mut simulator = Simulator::from_seed(1);
+        simulator.add_events(&network.start(flows[0].src_addr, flows[0].start));
+
+        while let Some(event) = simulator.pop() {
+            let now = event.due_time;
+            if now >= scenario.duration() {
+                break;
+            }
+            let events = network.process_event(event, now, &mut simulator.rng);
+            simulator.add_events(&events);
+        }
+        assert!(
+            network
+                .get_ref_terminal_by_addr(flows[0].dst_addr)
+                .get_delivered_packets()
+                > 0
+        );
+    }
+}
@@ -15,22 +15,61 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+mod protocol;
+mod rto;
+
 use super::address::Address;
 use super::link::AttachedLink;
-use super::packet::Packet;
+use super::packet::{Ack, Packet, PacketKind, SackBlock, SackBlocks, MAX_SACK_BLOCKS};
 use super::{Event, LinkAddress};
-use crate::simulator::{Payload, Target, Timeout};
+use crate::simulator::{AckTimeout, Payload, Target, Timeout};
 use eee_hyst::Time;
-use log::{debug, info, trace};
-use std::cmp::max;
+use log::{debug, info, trace, warn};
+use rand::RngCore;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub use protocol::{AckMode, AckPolicy, Protocol};
+pub use rto::{RtoEvent, RtoTraceEntry};
+
+use rto::RtoEstimator;
 
 pub type TerminalAddress = Address;
 
+#[derive(Clone, Copy, Debug)]
+enum Retransmission {
+    Timeout,
+    Nak,
+}
+
+/// Old packet taken as new data because its sequence number wrapped around
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct WrongAcceptance {
+    time: Time,
+    wire_seqno: u64,
+    abs_seqno: u64,
+    taken_as: u64,
+}
+
+/// How many of them show_stats() lists
+const SHOWN_WRONG_ACCEPTANCES: usize = 10;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct Terminal {
     header_size: u32,
     payload_size: u32,
     tx_window: u64,
+    protocol: Protocol,
+    seq_bits: Option<u32>,
+    nak: bool,
+    initial_rto: Option<Time>,
+    min_rto: Option<Time>,
+    ack_mode: Option<AckMode>,
+    ack_policy: AckPolicy,
+    piggyback_wait: Option<Time>,
+    sack_blocks: usize,
+    sack_block_size: u32,
+    fed: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -39,12 +78,53 @@ pub struct AttachedTerminal {
     header_size: u32,
     payload_size: u32,
     tx_window: u64,
+    protocol: Protocol,
+    seq_modulus: Option<u64>, // None for unbounded sequence numbers
+    nak: bool,
+    ack_mode: AckMode,
+    cumulative_dup_acks: bool, // Answer duplicate and out-of-order packets with the cumulative ACK
+    ack_policy: AckPolicy,
+    piggyback_wait: Option<Time>, // How long an ACK may wait for outgoing data
+    sack_blocks: usize,           // Maximum number of SACK blocks per ACK, none if zero
+    sack_block_size: u32,         // Header bytes taken by each SACK block
     pub link_addr: LinkAddress,
+    pub peer_addr: TerminalAddress, // The other end of the connection, maybe several hops away
+    path_delay: Time,               // Round trip over the links past the first one
     last_acked: u64,
     last_sent: u64,
     last_recv: u64,
+    last_transmitted: u64,
+    available: Option<u64>, // Last packet handed over to be sent, None for an endless supply
+    credit: Option<u64>,    // Last packet the relay at the other end has room for, if any
+    timers: BTreeMap<u64, Time>, // Deadline of the running retransmission timers
+    acked: BTreeSet<u64>,   // Acknowledged out of order (Selective Repeat)
+    buffered: BTreeSet<u64>, // Received out of order (Selective Repeat)
+    nak_sent: Option<u64>,  // Last missing packet requested with a NAK
+    pending_ack: Option<(Option<u64>, Packet)>, // Deferred ACK and the data that triggered it
+    unacked: u64,           // Packets received since the last ACK
+    ack_timer: Option<Time>, // Deadline of the delayed ACK
+    sent_at: BTreeMap<u64, (Time, bool)>, // Last departure and whether it was retransmitted
+    rto: Option<RtoEstimator>, // None to use the link's oracle timeout
+    min_rtt: Option<Time>,  // Shortest RTT measured on packets sent just once
 
-    last_tx_sched: Time,
+    // Statistics
+    duplicates: u64,
+    wrongly_accepted: Vec<WrongAcceptance>,
+    corrupted_received: u64,  // Processed as if they were fine
+    corrupted_delivered: u64, // Data handed over as new, with the wrong contents
+    delivered_base: u64,      // Every packet up to this one was delivered
+    delivered: BTreeSet<u64>, // Delivered packets beyond delivered_base
+    departures: Vec<Time>,    // First transmission of every packet
+    deliveries: Vec<Time>,    // When every packet was delivered in order
+    timeout_retransmissions: u64,
+    nak_retransmissions: u64,
+    spurious_retransmissions: u64, // Acknowledged too soon for the retransmission to have arrived
+    cumulatively_acked: u64,       // Packets whose own ACK did not make it before a later one
+    sent_sack_blocks: u64,
+    received_data: u64,
+    sent_acks: u64,
+    sent_ack_bytes: u64,
+    piggybacked_acks: u64,
 }
 
 impl Terminal {
@@ -53,162 +133,1110 @@ impl Terminal {
             header_size,
             payload_size,
             tx_window: u64::from(tx_window),
+            protocol: Protocol::default(),
+            seq_bits: None,
+            nak: false,
+            initial_rto: None,
+            min_rto: None,
+            ack_mode: None,
+            ack_policy: AckPolicy::default(),
+            piggyback_wait: None,
+            sack_blocks: 0,
+            sack_block_size: 0,
+            fed: false,
+        }
+    }
+
+    pub fn with_protocol(self, protocol: Protocol) -> Terminal {
+        Terminal { protocol, ..self }
+    }
+
+    /// Limits the sequence numbers on the wire to `seq_bits` bits, overriding the protocol's own width
+    pub fn with_seq_bits(self, seq_bits: u32) -> Terminal {
+        Terminal {
+            seq_bits: Some(seq_bits),
+            ..self
+        }
+    }
+
+    /// Makes the receiver request missing packets with a NAK as soon as it detects a gap
+    pub fn with_naks(self) -> Terminal {
+        Terminal { nak: true, ..self }
+    }
+
+    /// Estimates the retransmission timeout from the RTT samples instead of using the link's
+    /// parameters, starting from `initial_rto`
+    pub fn with_adaptive_rto(self, initial_rto: Time) -> Terminal {
+        Terminal {
+            initial_rto: Some(initial_rto),
+            ..self
         }
     }
 
+    /// Keeps the adaptive retransmission timeout from going below `min_rto`
+    pub fn with_min_rto(self, min_rto: Time) -> Terminal {
+        Terminal {
+            min_rto: Some(min_rto),
+            ..self
+        }
+    }
+
+    /// Overrides the protocol's default ACK semantics. Cumulative ACKs then also answer
+    /// duplicate and out-of-order packets, instead of just acknowledging each duplicate on its
+    /// own.
+    pub fn with_ack_mode(self, ack_mode: AckMode) -> Terminal {
+        Terminal {
+            ack_mode: Some(ack_mode),
+            ..self
+        }
+    }
+
+    /// Decides when the receiver sends its ACKs
+    pub fn with_ack_policy(self, ack_policy: AckPolicy) -> Terminal {
+        Terminal { ack_policy, ..self }
+    }
+
+    /// Carries the ACKs in the data sent back, waiting up to `wait` for it before sending an
+    /// ACK on its own
+    pub fn with_piggybacking(self, wait: Time) -> Terminal {
+        Terminal {
+            piggyback_wait: Some(wait),
+            ..self
+        }
+    }
+
+    /// Reports up to `sack_blocks` ranges of packets received out of order in every ACK, each
+    /// of them taking `block_size` extra header bytes
+    pub fn with_sack(self, sack_blocks: usize, block_size: u32) -> Terminal {
+        Terminal {
+            sack_blocks: sack_blocks.min(MAX_SACK_BLOCKS),
+            sack_block_size: block_size,
+            ..self
+        }
+    }
+
+    /// Only sends the data handed over with feed(), as the outgoing half of a relay, instead
+    /// of having an endless supply
+    pub fn with_fed_data(self) -> Terminal {
+        Terminal { fed: true, ..self }
+    }
+
+    /// Tells whether the ACK policy or piggybacking would hold back ACKs that only cover a
+    /// single packet
+    pub fn defers_individual_acks(&self) -> bool {
+        (self.ack_policy.defers() || self.piggyback_wait.is_some())
+            && self
+                .ack_mode
+                .unwrap_or_else(|| self.protocol.default_ack_mode())
+                == AckMode::Individual
+    }
+
+    fn seq_modulus(&self) -> Option<u64> {
+        self.seq_bits
+            .or_else(|| self.protocol.seq_bits())
+            .map(|bits| 1 << bits)
+    }
+
+    /// Largest window that avoids confusing new and old packets, if sequence numbers are bounded
+    pub fn max_window(&self) -> Option<u64> {
+        self.seq_modulus()
+            .map(|modulus| self.protocol.max_window(modulus))
+    }
+
     pub fn attach_to_link(
         &self,
         self_addr: TerminalAddress,
         link_addr: LinkAddress,
+        peer_addr: TerminalAddress,
     ) -> AttachedTerminal {
         AttachedTerminal {
             addr: self_addr,
             link_addr,
+            peer_addr,
+            path_delay: Time(0),
             header_size: self.header_size,
             payload_size: self.payload_size,
             tx_window: self.tx_window,
+            protocol: self.protocol,
+            seq_modulus: self.seq_modulus(),
+            nak: self.nak,
+            ack_mode: self
+                .ack_mode
+                .unwrap_or_else(|| self.protocol.default_ack_mode()),
+            cumulative_dup_acks: self.ack_mode.is_some(),
+            ack_policy: self.ack_policy,
+            piggyback_wait: self.piggyback_wait,
+            sack_blocks: self.sack_blocks,
+            sack_block_size: self.sack_block_size,
             last_acked: 0,
-            last_sent: self.tx_window, // A trick to not have to modify the terminal at start
+            // A trick to not have to modify the terminal at start
+            last_sent: if self.fed { 0 } else { self.tx_window },
             last_recv: 0,
-            last_tx_sched: Time(0),
+            last_transmitted: 0,
+            available: if self.fed { Some(0) } else { None },
+            credit: None,
+            timers: BTreeMap::new(),
+            acked: BTreeSet::new(),
+            buffered: BTreeSet::new(),
+            duplicates: 0,
+            wrongly_accepted: Vec::new(),
+            corrupted_received: 0,
+            corrupted_delivered: 0,
+            delivered_base: 0,
+            delivered: BTreeSet::new(),
+            departures: Vec::new(),
+            deliveries: Vec::new(),
+            nak_sent: None,
+            pending_ack: None,
+            unacked: 0,
+            ack_timer: None,
+            sent_at: BTreeMap::new(),
+            rto: self
+                .initial_rto
+                .map(|initial_rto| RtoEstimator::new(initial_rto, self.min_rto.unwrap_or(Time(0)))),
+            min_rtt: None,
+            timeout_retransmissions: 0,
+            nak_retransmissions: 0,
+            spurious_retransmissions: 0,
+            cumulatively_acked: 0,
+            sent_sack_blocks: 0,
+            received_data: 0,
+            sent_acks: 0,
+            sent_ack_bytes: 0,
+            piggybacked_acks: 0,
         }
     }
 }
 
 impl AttachedTerminal {
-    fn get_dst_address(&self, link: &AttachedLink) -> TerminalAddress {
-        if self.addr == link.src_addr {
-            link.dst_addr
-        } else {
-            link.src_addr
-        }
-    }
-
     pub fn start(&self, now: Time) -> Vec<Event> {
         (1..=self.last_sent)
             .map(|seqno| Event {
                 due_time: now + Time(seqno), // FIXME: Just a hack to make them timeout orderly
-                target: Target::Terminal(self.addr),
+                target: Target::Node(self.addr),
                 kind: Timeout(seqno),
             })
             .collect()
     }
 
+    /// Makes the fixed timeout wait for the round trip over another `link` of the path to the
+    /// peer, crossed from `hop_src`
+    pub fn add_hop(&mut self, link: &AttachedLink, hop_src: TerminalAddress) {
+        let packet = Packet {
+            kind: PacketKind::Data,
+            seqno: 0,
+            abs_seqno: 0,
+            header_size: self.header_size,
+            payload_size: self.payload_size,
+            src_addr: self.addr,
+            hop_src,
+            dst_addr: self.peer_addr,
+            ack: None,
+            sack: SackBlocks::default(),
+            corrupted: false,
+        };
+
+        self.path_delay = self.path_delay + link.tx(packet) + link.calc_timeout(packet);
+    }
+
+    fn wire_seqno(&self, seqno: u64) -> u64 {
+        self.seq_modulus.map_or(seqno, |modulus| seqno % modulus)
+    }
+
+    /// Smallest sequence number not below `base` that is sent as `wire_seqno`
+    fn unwrap_seqno(&self, wire_seqno: u64, base: u64) -> u64 {
+        match self.seq_modulus {
+            Some(modulus) => base + (wire_seqno + modulus - base % modulus) % modulus,
+            None => wire_seqno,
+        }
+    }
+
+    fn send(
+        &mut self,
+        packet: Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Option<Event> {
+        info!("{} sending {}", now.as_secs(), packet);
+        link.enqueue(packet, now, rng).map(|delivery_time| Event {
+            due_time: delivery_time,
+            target: Target::Link(self.link_addr),
+            kind: Payload(packet),
+        })
+    }
+
     fn transmit(
         &mut self,
         seqno: u64,
         dst_addr: TerminalAddress,
         now: Time,
-        payload_size: u32,
-        link: &AttachedLink,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
     ) -> Vec<Event> {
         let mut res = Vec::with_capacity(2);
 
+        let (ack, sack) = match (self.piggyback_wait, self.pending_ack) {
+            (Some(_), Some((received, _))) => match self.take_ack(received) {
+                Some((ack, sack)) => {
+                    self.piggybacked_acks += 1;
+                    (Some(ack), sack)
+                }
+                None => (None, SackBlocks::default()),
+            },
+            _ => (None, SackBlocks::default()),
+        };
+
         let p = Packet {
-            seqno,
-            header_size: self.header_size,
-            payload_size,
+            kind: PacketKind::Data,
+            seqno: self.wire_seqno(seqno),
+            abs_seqno: seqno,
+            header_size: self.header_size + self.sack_block_size * sack.len() as u32,
+            payload_size: self.payload_size,
             src_addr: self.addr,
+            hop_src: self.addr,
             dst_addr,
+            ack,
+            sack,
+            corrupted: false,
         };
 
-        let delivery_time = self.advance_delivery_time(link, p, now);
+        if self.rto.is_none() && self.min_rtt.is_none() {
+            // The fixed timeout knows the round trip without any queueing from the start
+            self.min_rtt = Some(link.calc_timeout(p) + self.path_delay);
+        }
+        let tx_event = self.send(p, now, link, rng);
+        // Dropped packets time out as if they had left right away
+        let sent = tx_event.as_ref().map_or(now, |event| event.due_time);
+        if seqno > self.last_transmitted {
+            self.departures.push(now);
+        }
+        self.sent_at
+            .insert(seqno, (sent, seqno <= self.last_transmitted));
+        self.last_transmitted = max(self.last_transmitted, seqno);
 
-        if payload_size > 0 {
+        // Go-Back-N only times the oldest unacknowledged packet
+        if self.protocol != Protocol::GoBackN || self.timers.is_empty() {
+            let deadline = sent + self.retransmission_timeout(p, link);
+
+            self.timers.insert(seqno, deadline);
             res.push(Event {
-                due_time: delivery_time + link.calc_timeout(p),
-                target: Target::Terminal(self.addr),
+                due_time: deadline,
+                target: Target::Node(self.addr),
                 kind: Timeout(seqno),
             });
         }
-
-        info!("{} sending {}", now.as_secs(), p);
-        res.push(Event {
-            due_time: delivery_time,
-            target: Target::Link(self.link_addr),
-            kind: Payload(p),
-        });
+        res.extend(tx_event);
 
         res
     }
 
+    /// Acknowledges the arrival of a data packet, identified as `received` if it was not
+    /// discarded, at once or later on according to the ACK policy. `in_order` tells whether it
+    /// was new data that arrived in order.
+    fn acknowledge(
+        &mut self,
+        received: Option<u64>,
+        in_order: bool,
+        data: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        self.unacked += 1;
+        if !self.ack_policy.defers() || (!in_order && self.ack_policy.immediate_out_of_order) {
+            return self.ack_now(received, data, now, link, rng);
+        }
+
+        self.pending_ack = Some((received, *data));
+        if self.unacked >= self.ack_policy.every {
+            return self.ack_now(received, data, now, link, rng);
+        }
+
+        match self.ack_policy.delay {
+            Some(delay) => self.arm_ack_timer(now + delay),
+            None => Vec::new(),
+        }
+    }
+
+    /// Sends the ACK in the next data packet, if it leaves soon enough, or on its own
+    fn ack_now(
+        &mut self,
+        received: Option<u64>,
+        data: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        match self.piggyback_wait {
+            Some(wait) if self.last_transmitted > 0 => {
+                self.pending_ack = Some((received, *data));
+                self.arm_ack_timer(now + wait)
+            }
+            _ => self.send_ack(received, data, now, link, rng),
+        }
+    }
+
+    /// Makes sure the pending ACK is sent no later than `deadline`
+    fn arm_ack_timer(&mut self, deadline: Time) -> Vec<Event> {
+        if self.ack_timer.is_some_and(|timer| timer <= deadline) {
+            return Vec::new();
+        }
+
+        self.ack_timer = Some(deadline);
+        vec![Event {
+            due_time: deadline,
+            target: Target::Node(self.addr),
+            kind: AckTimeout,
+        }]
+    }
+
+    fn process_ack_timeout(
+        &mut self,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        if self.ack_timer != Some(now) {
+            trace!("{} Ignoring stale delayed ACK timeout", now.as_secs());
+            return Vec::new();
+        }
+
+        match self.pending_ack {
+            Some((received, data)) => self.send_ack(received, &data, now, link, rng),
+            None => Vec::new(),
+        }
+    }
+
+    /// Sends the ACK right now in a packet of its own, covering any pending one
+    fn send_ack(
+        &mut self,
+        received: Option<u64>,
+        data: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let (ack, sack) = match self.take_ack(received) {
+            Some(ack) => ack,
+            None => return Vec::new(),
+        };
+
+        let ack = Packet {
+            kind: PacketKind::Ack,
+            seqno: ack.seqno,
+            abs_seqno: ack.abs_seqno,
+            header_size: self.header_size + self.sack_block_size * sack.len() as u32,
+            payload_size: 0,
+            src_addr: self.addr,
+            hop_src: self.addr,
+            dst_addr: data.src_addr,
+            ack: None,
+            sack,
+            corrupted: false,
+        };
+        self.sent_acks += 1;
+        self.sent_ack_bytes += u64::from(ack.header_size);
+
+        self.send(ack, now, link, rng).into_iter().collect()
+    }
+
+    /// Clears the pending ACK and builds the one acknowledging `received`, if any, according
+    /// to the ACK semantics in use
+    fn take_ack(&mut self, received: Option<u64>) -> Option<(Ack, SackBlocks)> {
+        // A duplicate gets its own ACK, unless that would leave others unacknowledged
+        let own_ack = !self.cumulative_dup_acks && self.unacked <= 1;
+        self.pending_ack = None;
+        self.unacked = 0;
+        self.ack_timer = None;
+
+        let seqno = match (self.ack_mode, received) {
+            (AckMode::NextExpected, _) => self.last_recv + 1,
+            (AckMode::LastReceived, Some(seqno)) if own_ack => seqno,
+            (AckMode::LastReceived, _) => self.last_recv,
+            (AckMode::Individual, Some(seqno)) => seqno,
+            (AckMode::Individual, None) => return None,
+        };
+
+        let sack = self.sack_blocks(received);
+        self.sent_sack_blocks += sack.len() as u64;
+
+        Some((
+            Ack {
+                seqno: self.wire_seqno(seqno),
+                abs_seqno: seqno,
+            },
+            sack,
+        ))
+    }
+
+    /// Ranges of buffered packets, starting with the one holding the packet just `received`
+    fn sack_blocks(&self, received: Option<u64>) -> SackBlocks {
+        let mut ranges: Vec<SackBlock> = Vec::new();
+        for &seqno in self.buffered.iter() {
+            match ranges.last_mut() {
+                Some(range) if range.end + 1 == seqno => range.end = seqno,
+                _ => ranges.push(SackBlock {
+                    start: seqno,
+                    end: seqno,
+                }),
+            }
+        }
+
+        if let Some(pos) = received.and_then(|seqno| {
+            ranges
+                .iter()
+                .position(|range| range.start <= seqno && seqno <= range.end)
+        }) {
+            let range = ranges.remove(pos);
+            ranges.insert(0, range);
+        }
+
+        let mut sack = SackBlocks::default();
+        for range in ranges.into_iter().take(self.sack_blocks) {
+            sack.push(SackBlock {
+                start: self.wire_seqno(range.start),
+                end: self.wire_seqno(range.end),
+            });
+        }
+
+        sack
+    }
+
+    /// Requests the next expected packet, only once while it stays missing
+    fn negative_acknowledge(
+        &mut self,
+        data: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let seqno = self.last_recv + 1;
+
+        if !self.nak || self.nak_sent == Some(seqno) {
+            return Vec::new();
+        }
+
+        self.nak_sent = Some(seqno);
+        let nak = Packet {
+            kind: PacketKind::Nak,
+            seqno: self.wire_seqno(seqno),
+            abs_seqno: seqno,
+            header_size: self.header_size,
+            payload_size: 0,
+            src_addr: self.addr,
+            hop_src: self.addr,
+            dst_addr: data.src_addr,
+            ack: None,
+            sack: SackBlocks::default(),
+            corrupted: false,
+        };
+
+        self.send(nak, now, link, rng).into_iter().collect()
+    }
+
+    fn retransmit(
+        &mut self,
+        seqno: u64,
+        cause: Retransmission,
+        dst_addr: TerminalAddress,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let seqnos: Vec<u64> = match self.protocol {
+            Protocol::GoBackN => {
+                self.timers.clear();
+                (seqno..=self.last_transmitted).collect()
+            }
+            _ => vec![seqno],
+        };
+
+        let counter = match cause {
+            Retransmission::Timeout => &mut self.timeout_retransmissions,
+            Retransmission::Nak => &mut self.nak_retransmissions,
+        };
+        *counter += seqnos.len() as u64;
+
+        seqnos
+            .into_iter()
+            .flat_map(|seqno| self.transmit(seqno, dst_addr, now, link, rng))
+            .collect()
+    }
+
+    fn retransmission_timeout(&self, packet: Packet, link: &AttachedLink) -> Time {
+        match &self.rto {
+            Some(rto) => rto.rto(),
+            // Leave room for the longest ACK, with all its SACK blocks, and for the time the
+            // receiver may hold it back
+            None => {
+                let ack_wait = match self.ack_policy.delay {
+                    Some(delay) => delay,
+                    None => {
+                        Time(link.tx(packet).0 * (self.ack_policy.every.min(self.tx_window) - 1))
+                    }
+                };
+                let ack_wait = match self.piggyback_wait {
+                    // And then travel within a data packet
+                    Some(wait) => {
+                        ack_wait
+                            + wait
+                            + link.tx(Packet {
+                                header_size: 0,
+                                src_addr: packet.dst_addr,
+                                dst_addr: packet.src_addr,
+                                hop_src: link.other_end(packet.hop_src),
+                                ..packet
+                            })
+                    }
+                    None => ack_wait,
+                };
+                let packet = Packet {
+                    header_size: packet.header_size
+                        + self.sack_block_size * self.sack_blocks as u32,
+                    ..packet
+                };
+                // ACKs pile up if the reverse direction is slower than the forward one, or
+                // wait for the rest of the window when both share the medium
+                let ack = Packet {
+                    payload_size: 0,
+                    src_addr: packet.dst_addr,
+                    dst_addr: packet.src_addr,
+                    hop_src: link.other_end(packet.hop_src),
+                    ..packet
+                };
+                let ack_queue = if link.is_half_duplex() {
+                    Time((link.tx(packet).0 + link.tx(ack).0) * (self.tx_window - 1))
+                } else {
+                    Time(link.tx(ack).0.saturating_sub(link.tx(packet).0) * (self.tx_window - 1))
+                };
+
+                link.calc_timeout(packet) + ack_wait + ack_queue + self.path_delay
+            }
+        }
+    }
+
+    /// Feeds the RTT estimator, skipping retransmitted packets as in Karn's algorithm
+    fn measure_rtt(&mut self, seqno: u64, now: Time) {
+        let (departure, retransmitted) = match self.sent_at.get(&seqno) {
+            Some(&sent_at) => sent_at,
+            None => return,
+        };
+
+        if retransmitted {
+            if let Some(rto) = self.rto.as_mut() {
+                rto.discard_sample(now, seqno);
+            }
+        } else if departure <= now {
+            // Otherwise the packet is still on its way out, and the ACK is a stale one taken for
+            // it after the sequence numbers wrapped around
+            let rtt = Time(now.0 - departure.0);
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min(min_rtt, rtt)));
+            if let Some(rto) = self.rto.as_mut() {
+                rto.sample(now, seqno, rtt);
+            }
+        }
+    }
+
+    /// Counts the retransmitted packets among those just acknowledged whose ACK came sooner
+    /// than the shortest round trip known, so it must have been caused by an earlier copy
+    fn count_spurious_retransmissions(&mut self, now: Time) {
+        let min_rtt = match self.min_rtt {
+            Some(min_rtt) => min_rtt,
+            None => return,
+        };
+
+        self.spurious_retransmissions += self
+            .sent_at
+            .iter()
+            .filter(|&(seqno, &(sent, retransmitted))| {
+                retransmitted
+                    && (*seqno <= self.last_acked || self.acked.contains(seqno))
+                    && now < sent + min_rtt
+            })
+            .count() as u64;
+    }
+
     fn process_timeout(
         &mut self,
         dst_addr: TerminalAddress,
         seqno: u64,
         now: Time,
-        link: &AttachedLink,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
     ) -> Vec<Event> {
-        if seqno > self.last_acked {
-            debug!("Processing timeout {}", seqno);
-            self.transmit(seqno, dst_addr, now, self.payload_size, link)
-        } else {
+        if seqno > self.last_transmitted {
+            // Initial transmission, see start()
+            return self.transmit(seqno, dst_addr, now, link, rng);
+        }
+
+        if self.timers.get(&seqno) != Some(&now) {
             trace!(
-                "{} Ignoring timeout for {}, minimum is {}",
+                "{} Ignoring stale timeout for {}, minimum is {}",
                 now.as_secs(),
                 seqno,
                 self.last_acked + 1
             );
+            return Vec::new();
+        }
+
+        debug!("Processing timeout {}", seqno);
+        self.timers.remove(&seqno);
+        if let (Some(rto), Some(&(sent, _))) = (self.rto.as_mut(), self.sent_at.get(&seqno)) {
+            rto.backoff(now, seqno, sent);
+        }
+        self.retransmit(seqno, Retransmission::Timeout, dst_addr, now, link, rng)
+    }
+
+    fn process_nak(
+        &mut self,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        info!("{} received {}", now.as_secs(), packet);
+
+        let seqno = self.unwrap_seqno(packet.seqno, self.last_acked + 1);
+        if seqno > self.last_acked && seqno <= self.last_transmitted && !self.acked.contains(&seqno)
+        {
+            debug!("Retransmitting {} before its timeout", seqno);
+            self.retransmit(seqno, Retransmission::Nak, packet.src_addr, now, link, rng)
+        } else {
+            debug!(
+                "Ignoring NAK {}, outstanding packets are ({}, {}]",
+                packet.seqno, self.last_acked, self.last_transmitted
+            );
             Vec::new()
         }
     }
 
-    fn process_ack(&mut self, packet: &Packet, now: Time, link: &AttachedLink) -> Vec<Event> {
-        info!("{} ACK received {}", now.as_secs(), packet);
+    /// Processes `ack`, either received on its own or piggybacked in `packet`
+    fn process_ack(
+        &mut self,
+        ack: Ack,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        info!("{} received {}", now.as_secs(), packet);
+
+        let acked_seqno = match self.ack_mode {
+            AckMode::NextExpected => self.unwrap_seqno(ack.seqno, self.last_acked + 2),
+            AckMode::LastReceived | AckMode::Individual => {
+                self.unwrap_seqno(ack.seqno, self.last_acked + 1)
+            }
+        };
+        let seqno = match self.ack_mode {
+            AckMode::NextExpected => acked_seqno - 1,
+            AckMode::LastReceived | AckMode::Individual => acked_seqno,
+        };
 
-        if packet.seqno > self.last_acked && packet.seqno <= self.last_sent {
+        let valid = seqno > self.last_acked && seqno <= self.last_sent;
+        // A cumulative ACK may cover packets already reported in SACK blocks
+        let acked_new =
+            valid && (self.ack_mode != AckMode::Individual || !self.acked.contains(&seqno));
+        if acked_new {
             debug!("Current window: ({}, {}]", self.last_acked, self.last_sent);
-            self.last_acked = packet.seqno;
+            if ack.abs_seqno != acked_seqno {
+                warn!(
+                    "{} ACK {} wrongly taken as ACK {}",
+                    now.as_secs(),
+                    ack.abs_seqno,
+                    acked_seqno
+                );
+            }
+            self.measure_rtt(seqno, now);
+
+            if self.ack_mode == AckMode::Individual {
+                self.acked.insert(seqno);
+            } else {
+                self.cumulatively_acked += (self.last_acked + 1..seqno)
+                    .filter(|seqno| !self.acked.contains(seqno))
+                    .count() as u64;
+                self.last_acked = seqno;
+                self.acked = self.acked.split_off(&(seqno + 1));
+            }
+        }
 
-            let res = (self.last_sent + 1..=self.last_acked + self.tx_window)
-                .flat_map(|seqno| {
-                    self.transmit(seqno, packet.src_addr, now, self.payload_size, link)
-                })
-                .collect();
+        // SACK blocks may carry news even when the ACK itself is a duplicate
+        let sacked = self.process_sack(packet);
+        if !acked_new && !sacked {
+            debug!(
+                "Ignoring incorrect ack {}, expecting from ({}, {}]",
+                ack.seqno, self.last_acked, self.last_sent
+            );
 
-            self.last_sent = self.last_acked + self.tx_window;
+            return Vec::new();
+        }
 
-            debug!("Updated window: ({}, {}]", self.last_acked, self.last_sent);
+        while self.acked.remove(&(self.last_acked + 1)) {
+            self.last_acked += 1;
+        }
+        let mut res = self.update_timers(packet.src_addr, now, link);
 
-            res
+        res.extend(
+            (self.last_sent + 1..=self.window_end())
+                .flat_map(|seqno| self.transmit(seqno, packet.src_addr, now, link, rng)),
+        );
+
+        self.last_sent = self.window_end();
+
+        debug!("Updated window: ({}, {}]", self.last_acked, self.last_sent);
+
+        res
+    }
+
+    /// Last packet that can be sent, as far as the window, the data available and the room at
+    /// the other end allow
+    fn window_end(&self) -> u64 {
+        [self.available, self.credit]
+            .into_iter()
+            .flatten()
+            .fold(self.last_acked + self.tx_window, min)
+    }
+
+    /// Sends whatever the window now lets through
+    fn fill_window(
+        &mut self,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let res = (self.last_sent + 1..=self.window_end())
+            .flat_map(|seqno| self.transmit(seqno, self.peer_addr, now, link, rng))
+            .collect();
+        self.last_sent = max(self.last_sent, self.window_end());
+
+        res
+    }
+
+    /// Hands over every packet up to `available` to be sent, see Terminal::with_fed_data()
+    pub fn feed(
+        &mut self,
+        available: u64,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        self.available = Some(available);
+        self.fill_window(now, link, rng)
+    }
+
+    /// Holds back every packet past `credit`, as the relay at the other end has no room for them
+    pub fn set_credit(&mut self, credit: u64) {
+        self.credit = Some(credit);
+    }
+
+    /// Lets every packet up to `credit` be sent, once the relay at the other end made room
+    pub fn grant(
+        &mut self,
+        credit: u64,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        self.set_credit(credit);
+        self.fill_window(now, link, rng)
+    }
+
+    /// Marks the packets covered by the SACK blocks as received. Returns whether any of them
+    /// was not known to be received.
+    fn process_sack(&mut self, packet: &Packet) -> bool {
+        let mut updated = false;
+
+        for block in packet.sack.iter() {
+            let start = self.unwrap_seqno(block.start, self.last_acked + 1);
+            let end = self.unwrap_seqno(block.end, start);
+
+            for seqno in start..=end.min(self.last_transmitted) {
+                updated |= self.acked.insert(seqno);
+            }
+        }
+
+        updated
+    }
+
+    fn update_timers(
+        &mut self,
+        dst_addr: TerminalAddress,
+        now: Time,
+        link: &AttachedLink,
+    ) -> Vec<Event> {
+        self.count_spurious_retransmissions(now);
+        let (last_acked, acked) = (self.last_acked, &self.acked);
+        self.timers
+            .retain(|seqno, _| *seqno > last_acked && !acked.contains(seqno));
+        self.sent_at
+            .retain(|seqno, _| *seqno > last_acked && !acked.contains(seqno));
+
+        // The single Go-Back-N timer restarts while there is still outstanding data
+        if self.protocol != Protocol::GoBackN
+            || !self.timers.is_empty()
+            || self.last_acked >= self.last_transmitted
+        {
+            return Vec::new();
+        }
+
+        let seqno = self.last_acked + 1;
+        let deadline = now
+            + self.retransmission_timeout(
+                Packet {
+                    kind: PacketKind::Data,
+                    seqno: self.wire_seqno(seqno),
+                    abs_seqno: seqno,
+                    header_size: self.header_size,
+                    payload_size: self.payload_size,
+                    src_addr: self.addr,
+                    hop_src: self.addr,
+                    dst_addr,
+                    ack: None,
+                    sack: SackBlocks::default(),
+                    corrupted: false,
+                },
+                link,
+            );
+
+        self.timers.insert(seqno, deadline);
+        vec![Event {
+            due_time: deadline,
+            target: Target::Node(self.addr),
+            kind: Timeout(seqno),
+        }]
+    }
+
+    fn process_data(
+        &mut self,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        info!("{} received {}", now.as_secs(), packet);
+        self.received_data += 1;
+        match self.protocol {
+            Protocol::Hybrid | Protocol::GoBackN => {
+                self.process_data_in_order(packet, now, link, rng)
+            }
+            Protocol::SelectiveRepeat => self.process_data_selective(packet, now, link, rng),
+            Protocol::AlternatingBit | Protocol::StopAndWait => {
+                self.process_data_alternating(packet, now, link, rng)
+            }
+        }
+    }
+
+    fn accept(&mut self, packet: &Packet, seqno: u64, now: Time) {
+        if packet.corrupted {
+            self.corrupted_delivered += 1;
+        }
+
+        if packet.abs_seqno <= self.delivered_base || !self.delivered.insert(packet.abs_seqno) {
+            self.wrongly_accepted.push(WrongAcceptance {
+                time: now,
+                wire_seqno: packet.seqno,
+                abs_seqno: packet.abs_seqno,
+                taken_as: seqno,
+            });
+            warn!(
+                "{} Packet {} delivered again, wrongly accepted as new data {}",
+                now.as_secs(),
+                packet.abs_seqno,
+                seqno
+            );
+        }
+
+        while self.delivered.remove(&(self.delivered_base + 1)) {
+            self.delivered_base += 1;
+            self.deliveries.push(now);
+        }
+    }
+
+    /// Tells whether a received packet precedes the next expected one. With bounded sequence
+    /// numbers, anything farther than a window ahead must be old.
+    fn is_old(&self, wire_seqno: u64) -> bool {
+        let expected = self.last_recv + 1;
+        let seqno = self.unwrap_seqno(wire_seqno, expected);
+
+        match self.seq_modulus {
+            Some(_) => seqno - expected >= self.tx_window,
+            None => seqno < expected,
+        }
+    }
+
+    /// Sequence number of an old packet, see is_old()
+    fn unwrap_old_seqno(&self, wire_seqno: u64) -> u64 {
+        match self.seq_modulus {
+            Some(modulus) => self
+                .unwrap_seqno(wire_seqno, self.last_recv + 1)
+                .saturating_sub(modulus),
+            None => wire_seqno,
+        }
+    }
+
+    fn process_data_in_order(
+        &mut self,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let expected = self.last_recv + 1;
+
+        if packet.seqno == self.wire_seqno(expected) {
+            // New data
+            self.accept(packet, expected, now);
+            self.last_recv = expected;
+            self.acknowledge(Some(expected), true, packet, now, link, rng)
+        } else if self.is_old(packet.seqno) {
+            self.duplicates += 1;
+            self.acknowledge(
+                Some(self.unwrap_old_seqno(packet.seqno)),
+                false,
+                packet,
+                now,
+                link,
+                rng,
+            )
         } else {
             debug!(
-                "Ignoring incorrect ack {}, expecting from ({}, {}]",
-                packet.seqno, self.last_acked, self.last_sent
+                "Ignoring unexpected packet {}, expecting {}",
+                packet.seqno,
+                self.wire_seqno(expected)
             );
+            let mut res = if self.cumulative_dup_acks {
+                self.acknowledge(None, false, packet, now, link, rng)
+            } else {
+                Vec::new()
+            };
+            res.extend(self.negative_acknowledge(packet, now, link, rng));
 
-            Vec::new()
+            res
         }
     }
 
-    fn process_data(&mut self, packet: &Packet, now: Time, link: &AttachedLink) -> Vec<Event> {
-        info!("{} DATA received {}", now.as_secs(), packet);
-        if packet.seqno <= self.last_recv + 1 {
-            // New data
+    fn process_data_selective(
+        &mut self,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        let seqno = self.unwrap_seqno(packet.seqno, self.last_recv + 1);
+
+        if seqno <= self.last_recv || seqno > self.last_recv + self.tx_window {
+            if self.is_old(packet.seqno) {
+                // Its previous ACK might have been lost
+                self.duplicates += 1;
+                return self.acknowledge(
+                    Some(self.unwrap_old_seqno(packet.seqno)),
+                    false,
+                    packet,
+                    now,
+                    link,
+                    rng,
+                );
+            }
+
+            debug!(
+                "Ignoring packet {} outside the receive window ({}, {}]",
+                packet.seqno,
+                self.last_recv,
+                self.last_recv + self.tx_window
+            );
+            return self.acknowledge(None, false, packet, now, link, rng);
+        }
+
+        let new = self.buffered.insert(seqno);
+        if new {
+            self.accept(packet, seqno, now);
+            while self.buffered.remove(&(self.last_recv + 1)) {
+                self.last_recv += 1;
+            }
+            debug!(
+                "Receive window: ({}, {}], buffered: {:?}",
+                self.last_recv,
+                self.last_recv + self.tx_window,
+                self.buffered
+            );
+        } else {
+            self.duplicates += 1;
+        }
 
-            self.last_recv = max(self.last_recv, packet.seqno);
-            self.transmit(packet.seqno, packet.src_addr, now, 0, link)
+        let in_order = new && seqno == self.last_recv && self.buffered.is_empty();
+        let mut res = self.acknowledge(Some(seqno), in_order, packet, now, link, rng);
+        if !self.buffered.is_empty() {
+            res.extend(self.negative_acknowledge(packet, now, link, rng));
+        }
+
+        res
+    }
+
+    fn process_data_alternating(
+        &mut self,
+        packet: &Packet,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        // The sequence number on the wire is the only way to tell new data from duplicates
+        let new = packet.seqno == self.wire_seqno(self.last_recv + 1);
+        if new {
+            self.accept(packet, self.last_recv + 1, now);
+            self.last_recv += 1;
         } else {
             debug!(
-                "Ignoring unexpected packet {}, expecting {}",
+                "Discarding duplicate packet {}, expecting {}",
                 packet.seqno,
-                self.last_recv + 1
+                self.wire_seqno(self.last_recv + 1)
             );
-            vec![]
+            self.duplicates += 1;
         }
+
+        self.acknowledge(Some(self.last_recv), new, packet, now, link, rng)
     }
 
-    pub fn process(&mut self, event: Event, now: Time, link: &AttachedLink) -> Vec<Event> {
+    pub fn process(
+        &mut self,
+        event: Event,
+        now: Time,
+        link: &mut AttachedLink,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Event> {
+        if let Payload(ref packet) = event.kind {
+            if packet.corrupted {
+                self.corrupted_received += 1;
+                warn!("{} Silently accepting corrupted {}", now.as_secs(), packet);
+            }
+        }
+
         match event.kind {
-            Payload(ref packet) => {
-                if packet.payload_size == 0 {
-                    self.process_ack(packet, now, link)
-                } else {
-                    self.process_data(packet, now, link)
+            Payload(ref packet) => match packet.kind {
+                PacketKind::Data => {
+                    // Data first, so that anything sent because of the ACK carries its own
+                    let mut res = self.process_data(packet, now, link, rng);
+                    if let Some(ack) = packet.ack {
+                        res.extend(self.process_ack(ack, packet, now, link, rng));
+                    }
+
+                    res
                 }
-            }
+                PacketKind::Ack => {
+                    let ack = Ack {
+                        seqno: packet.seqno,
+                        abs_seqno: packet.abs_seqno,
+                    };
+                    self.process_ack(ack, packet, now, link, rng)
+                }
+                PacketKind::Nak => self.process_nak(packet, now, link, rng),
+                PacketKind::Syn | PacketKind::Fin => {
+                    debug!("Ignoring control packet {}", packet);
+                    Vec::new()
+                }
+            },
 
-            Timeout(seqno) => self.process_timeout(self.get_dst_address(link), seqno, now, link),
+            Timeout(seqno) => self.process_timeout(self.peer_addr, seqno, now, link, rng),
+            AckTimeout => self.process_ack_timeout(now, link, rng),
         }
     }
 
@@ -216,11 +1244,326 @@ impl AttachedTerminal {
         self.last_acked
     }
 
-    fn advance_delivery_time(&mut self, link: &AttachedLink, packet: Packet, now: Time) -> Time {
-        let tx_time = link.tx(packet);
+    pub fn get_window(&self) -> u64 {
+        self.tx_window
+    }
+
+    /// Payload bytes of every data packet sent
+    pub fn get_payload_size(&self) -> u32 {
+        self.payload_size
+    }
+
+    pub fn get_retransmissions(&self) -> u64 {
+        self.timeout_retransmissions + self.nak_retransmissions
+    }
+
+    /// Packets delivered in order to the application
+    pub fn get_delivered_packets(&self) -> u64 {
+        self.delivered_base
+    }
+
+    /// When every packet sent was first transmitted
+    pub fn get_departures(&self) -> &[Time] {
+        &self.departures
+    }
+
+    /// When every packet received was delivered in order to the application
+    pub fn get_deliveries(&self) -> &[Time] {
+        &self.deliveries
+    }
+
+    /// Bytes sent in ACKs, SACK blocks included
+    pub fn get_ack_traffic(&self) -> u64 {
+        self.sent_ack_bytes
+    }
+
+    /// Header bytes spent in SACK blocks
+    pub fn get_sack_overhead(&self) -> u64 {
+        self.sent_sack_blocks * u64::from(self.sack_block_size)
+    }
+
+    /// Evolution of the adaptive retransmission timeout, empty if not in use
+    pub fn get_rto_trace(&self) -> &[RtoTraceEntry] {
+        self.rto.as_ref().map_or(&[], |rto| rto.trace())
+    }
+
+    pub fn show_stats(&self) {
+        if self.last_transmitted > 0 {
+            println!(
+                "Retransmitted {} packets ({} after a timeout, {} after a NAK), {} of them spurious",
+                self.timeout_retransmissions + self.nak_retransmissions,
+                self.timeout_retransmissions,
+                self.nak_retransmissions,
+                self.spurious_retransmissions
+            );
+            if self.ack_mode != AckMode::Individual {
+                println!(
+                    "Acknowledged {} packets with a later cumulative ACK",
+                    self.cumulatively_acked
+                );
+            }
+            if let Some(rto) = &self.rto {
+                println!("Final RTO: {} s", rto.rto().as_secs());
+            }
+        }
+        if self.sent_sack_blocks > 0 {
+            println!(
+                "Sent {} SACK blocks ({} bytes)",
+                self.sent_sack_blocks,
+                self.get_sack_overhead()
+            );
+        }
+        if self.last_recv > 0 {
+            println!(
+                "Received {} duplicate packets, {} wrongly accepted as new data",
+                self.duplicates,
+                self.wrongly_accepted.len()
+            );
+            for wrong in self.wrongly_accepted.iter().take(SHOWN_WRONG_ACCEPTANCES) {
+                println!(
+                    "{} s: packet {}, sent as {}, wrongly accepted as new data {}",
+                    wrong.time.as_secs(),
+                    wrong.abs_seqno,
+                    wrong.wire_seqno,
+                    wrong.taken_as
+                );
+            }
+            if self.wrongly_accepted.len() > SHOWN_WRONG_ACCEPTANCES {
+                println!(
+                    "... and {} more",
+                    self.wrongly_accepted.len() - SHOWN_WRONG_ACCEPTANCES
+                );
+            }
+        }
+        if self.corrupted_received > 0 {
+            println!(
+                "Silently accepted {} corrupted packets, {} of them delivered as new data",
+                self.corrupted_received, self.corrupted_delivered
+            );
+        }
+        if self.received_data > 0 {
+            println!(
+                "Sent {} ACKs ({} bytes) for {} data packets",
+                self.sent_acks, self.sent_ack_bytes, self.received_data
+            );
+            if self.piggyback_wait.is_some() {
+                println!("Piggybacked {} ACKs in data packets", self.piggybacked_acks);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BitErrors, Link, LossPattern, Network, PacketErrors};
+    use super::*;
+    use crate::simulator::Simulator;
+
+    /// Sends from `sender` to `receiver` over `link` for `duration` seconds, or until
+    /// `packets` are delivered, and returns both ends
+    fn simulate(
+        sender: Terminal,
+        receiver: Terminal,
+        link: Link,
+        duration: f64,
+        packets: u64,
+        seed: u64,
+    ) -> (AttachedTerminal, AttachedTerminal) {
+        let mut network = Network::default();
+        let (src_addr, dst_addr, _) = network.add_link_and_terminals(sender, receiver, link);
+        let mut simulator = Simulator::from_seed(seed);
+        simulator.add_events(&network.start(src_addr, Time(0)));
+
+        while let Some(event) = simulator.pop() {
+            let now = event.due_time;
+            if now >= Time::from_secs(duration)
+                || network
+                    .get_ref_terminal_by_addr(dst_addr)
+                    .get_delivered_packets()
+                    >= packets
+            {
+                break;
+            }
+            let events = network.process_event(event, now, &mut simulator.rng);
+            simulator.add_events(&events);
+        }
+
+        (
+            network.get_ref_terminal_by_addr(src_addr).clone(),
+            network.get_ref_terminal_by_addr(dst_addr).clone(),
+        )
+    }
+
+    /// 10 Mb/s link losing a fraction `per` of the data packets, but no ACK
+    fn lossy_link(per: f64) -> Link {
+        Link::create(1e7, Time::from_secs(1e-3), PacketErrors::create(per))
+            .with_reverse_error_model(BitErrors::create(0.0))
+    }
+
+    fn ends(protocol: Protocol, tx_window: u16) -> (Terminal, Terminal) {
+        (
+            Terminal::create(40, 1460, tx_window).with_protocol(protocol),
+            Terminal::create(40, 0, tx_window).with_protocol(protocol),
+        )
+    }
+
+    fn attached(terminal: Terminal) -> AttachedTerminal {
+        terminal.attach_to_link(
+            TerminalAddress::create(0),
+            LinkAddress::create(0),
+            TerminalAddress::create(1),
+        )
+    }
+
+    #[test]
+    fn rtt_comes_from_packets_sent_once() {
+        let mut terminal = attached(Terminal::create(40, 1460, 4));
+        terminal.sent_at.insert(1, (Time(100), false));
+        terminal.sent_at.insert(2, (Time(100), true));
+
+        terminal.measure_rtt(2, Time(130));
+        assert_eq!(terminal.min_rtt, None);
+        terminal.measure_rtt(1, Time(150));
+        assert_eq!(terminal.min_rtt, Some(Time(50)));
+    }
+
+    #[test]
+    fn stale_acks_for_packets_still_being_sent_are_no_rtt_sample() {
+        let mut terminal =
+            attached(Terminal::create(40, 1460, 4).with_adaptive_rto(Time::from_secs(1.0)));
+        terminal.sent_at.insert(1, (Time(100), false));
+
+        terminal.measure_rtt(1, Time(50));
+        assert_eq!(terminal.min_rtt, None);
+    }
+
+    #[test]
+    fn wraparound_demo_does_not_panic() {
+        let (sender, receiver) = ends(Protocol::Hybrid, 8);
+        let link = Link::create(1e6, Time::from_secs(1e-3), BitErrors::create(1e-5));
+
+        let (sender, _) = simulate(
+            sender.with_seq_bits(3),
+            receiver.with_seq_bits(3),
+            link,
+            2.0,
+            u64::MAX,
+            2,
+        );
+        assert!(sender.get_transmitted_packets() > 0);
+    }
+
+    #[test]
+    fn oversized_windows_accept_old_packets_as_new() {
+        let (sender, receiver) = ends(Protocol::GoBackN, 8);
+
+        let (_, receiver) = simulate(
+            sender.with_seq_bits(3),
+            receiver.with_seq_bits(3),
+            Link::create(1e7, Time::from_secs(1e-3), PacketErrors::create(0.2)),
+            10.0,
+            200,
+            1,
+        );
+        assert!(!receiver.wrongly_accepted.is_empty());
+        let wrong = receiver.wrongly_accepted[0];
+        assert_eq!(wrong.wire_seqno, wrong.abs_seqno % 8);
+        assert_ne!(wrong.abs_seqno, wrong.taken_as);
+    }
+
+    #[test]
+    fn windows_within_the_sequence_space_accept_no_old_packets() {
+        let (sender, receiver) = ends(Protocol::GoBackN, 7);
+
+        let (_, receiver) = simulate(
+            sender.with_seq_bits(3),
+            receiver.with_seq_bits(3),
+            Link::create(1e7, Time::from_secs(1e-3), PacketErrors::create(0.2)),
+            10.0,
+            200,
+            1,
+        );
+        assert!(receiver.wrongly_accepted.is_empty());
+        assert!(receiver.get_delivered_packets() >= 200);
+    }
+
+    #[test]
+    fn selective_repeat_resends_only_lost_packets() {
+        let (sender, receiver) = ends(Protocol::SelectiveRepeat, 8);
+        let (sr_sender, receiver) = simulate(sender, receiver, lossy_link(0.1), 10.0, 100, 1);
+        assert!(receiver.get_delivered_packets() >= 100);
+        assert_eq!(receiver.duplicates, 0);
+
+        let (sender, receiver) = ends(Protocol::GoBackN, 8);
+        let (gbn_sender, receiver) = simulate(sender, receiver, lossy_link(0.1), 10.0, 100, 1);
+        assert!(receiver.get_delivered_packets() >= 100);
+
+        assert!(sr_sender.get_retransmissions() < gbn_sender.get_retransmissions());
+    }
+
+    #[test]
+    fn alternating_bit_discards_duplicates() {
+        let (sender, receiver) = ends(Protocol::AlternatingBit, 1);
+        // Lose every third ACK, so the data gets sent twice
+        let link = Link::create(1e7, Time::from_secs(1e-3), BitErrors::create(0.0))
+            .with_reverse_error_model(LossPattern::parse("001").unwrap());
+
+        let (_, receiver) = simulate(sender, receiver, link, 10.0, 100, 1);
+        assert_eq!(receiver.get_delivered_packets(), 100);
+        assert!(receiver.duplicates > 0);
+        assert!(receiver.wrongly_accepted.is_empty());
+    }
+
+    #[test]
+    fn naks_retransmit_before_the_timeout() {
+        let (sender, receiver) = ends(Protocol::GoBackN, 8);
+
+        let (sender, receiver) = simulate(
+            sender.with_naks(),
+            receiver.with_naks(),
+            lossy_link(0.1),
+            10.0,
+            100,
+            1,
+        );
+        assert!(receiver.get_delivered_packets() >= 100);
+        assert!(sender.nak_retransmissions > 0);
+    }
+
+    #[test]
+    fn acks_go_once_every_few_packets() {
+        let (sender, receiver) = ends(Protocol::GoBackN, 8);
+        let policy = AckPolicy {
+            every: 4,
+            ..AckPolicy::default()
+        };
+
+        let (_, receiver) = simulate(
+            sender.with_ack_policy(policy),
+            receiver.with_ack_policy(policy),
+            lossy_link(0.0),
+            10.0,
+            100,
+            1,
+        );
+        assert_eq!(receiver.get_delivered_packets(), 100);
+        assert_eq!(receiver.sent_acks, 25);
+    }
 
-        self.last_tx_sched = max(now, self.last_tx_sched) + tx_time;
+    #[test]
+    fn sack_blocks_report_buffered_packets() {
+        let (sender, receiver) = ends(Protocol::SelectiveRepeat, 8);
 
-        self.last_tx_sched
+        let (_, receiver) = simulate(
+            sender.with_sack(2, 8),
+            receiver.with_sack(2, 8),
+            lossy_link(0.1),
+            10.0,
+            100,
+            1,
+        );
+        assert!(receiver.get_delivered_packets() >= 100);
+        assert!(receiver.sent_sack_blocks > 0);
+        assert_eq!(receiver.get_sack_overhead(), 8 * receiver.sent_sack_blocks);
     }
 }
@@ -0,0 +1,119 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use clap::ValueEnum;
+use eee_hyst::Time;
+
+/// ARQ algorithm run by a terminal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Protocol {
+    /// In-order receiver with a timer per packet that only retransmits the expired packet
+    #[default]
+    #[value(name = "hybrid")]
+    Hybrid,
+    /// Go-Back-N: a single timer for the oldest unacknowledged packet whose expiry resends the whole window
+    #[value(name = "gbn")]
+    GoBackN,
+    /// Selective Repeat: the receiver buffers out-of-order packets and acknowledges each of them
+    #[value(name = "sr")]
+    SelectiveRepeat,
+    /// Stop-and-Wait with a single bit of sequence number on the wire
+    #[value(name = "abp")]
+    AlternatingBit,
+    /// Stop-and-Wait without any sequence number on the wire
+    #[value(name = "saw")]
+    StopAndWait,
+}
+
+/// Meaning of the sequence number carried by an ACK
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum AckMode {
+    /// Cumulative, carrying the next expected sequence number
+    #[value(name = "next")]
+    NextExpected,
+    /// Cumulative, carrying the last sequence number received in order
+    #[value(name = "last")]
+    LastReceived,
+    /// Acknowledges just the packet that carries the same sequence number
+    #[value(name = "individual")]
+    Individual,
+}
+
+/// When the receiver sends its ACKs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AckPolicy {
+    /// Acknowledge once every this many packets received in order
+    pub every: u64,
+    /// Send a pending ACK at most this long after the packet that triggered it
+    pub delay: Option<Time>,
+    /// Acknowledge duplicate and out-of-order packets at once
+    pub immediate_out_of_order: bool,
+}
+
+impl Default for AckPolicy {
+    fn default() -> Self {
+        AckPolicy {
+            every: 1,
+            delay: None,
+            immediate_out_of_order: true,
+        }
+    }
+}
+
+impl AckPolicy {
+    /// Tells whether some ACKs are not sent as soon as data arrives
+    pub fn defers(&self) -> bool {
+        self.every > 1 || self.delay.is_some()
+    }
+}
+
+impl Protocol {
+    pub fn default_ack_mode(&self) -> AckMode {
+        match self {
+            Protocol::SelectiveRepeat => AckMode::Individual,
+            _ => AckMode::LastReceived,
+        }
+    }
+
+    /// Tells whether the receiver keeps out-of-order packets, which SACK blocks report
+    pub fn buffers_out_of_order(&self) -> bool {
+        matches!(self, Protocol::SelectiveRepeat)
+    }
+
+    pub fn is_stop_and_wait(&self) -> bool {
+        matches!(self, Protocol::AlternatingBit | Protocol::StopAndWait)
+    }
+
+    /// Width of the sequence number field imposed by the protocol, if any
+    pub fn seq_bits(&self) -> Option<u32> {
+        match self {
+            Protocol::AlternatingBit => Some(1),
+            Protocol::StopAndWait => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Largest window that works with `modulus` different sequence numbers
+    pub fn max_window(&self, modulus: u64) -> u64 {
+        match self {
+            Protocol::Hybrid | Protocol::GoBackN => modulus - 1,
+            Protocol::SelectiveRepeat => modulus / 2,
+            Protocol::AlternatingBit => modulus / 2,
+            Protocol::StopAndWait => 1, // Nothing to wrap around, but a packet at a time
+        }
+    }
+}
@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2026 Miguel Rodríguez Pérez <miguel@det.uvigo.gal>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use eee_hyst::Time;
+use std::cmp::{max, min};
+
+const MAX_RTO: f64 = 60.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtoEvent {
+    /// A new RTT measurement
+    Sample(Time),
+    /// An RTT measurement discarded by Karn's algorithm, as the packet had been retransmitted
+    Ambiguous,
+    /// The timer expired and the RTO was doubled
+    Backoff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RtoTraceEntry {
+    pub time: Time,
+    pub seqno: u64,
+    pub event: RtoEvent,
+    pub srtt: Option<Time>,
+    pub rttvar: Time,
+    pub rto: Time,
+}
+
+/// Jacobson/Karels retransmission timeout estimator, as in RFC 6298 but without its clock
+/// granularity term, and with a configurable floor in place of its 1 s minimum
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RtoEstimator {
+    srtt: Option<Time>,
+    rttvar: Time,
+    rto: Time,
+    min_rto: Time,
+    last_backoff: Option<Time>,
+    trace: Vec<RtoTraceEntry>,
+}
+
+impl RtoEstimator {
+    pub fn new(initial_rto: Time, min_rto: Time) -> RtoEstimator {
+        RtoEstimator {
+            srtt: None,
+            rttvar: Time(0),
+            rto: initial_rto,
+            min_rto,
+            last_backoff: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn rto(&self) -> Time {
+        self.rto
+    }
+
+    pub fn trace(&self) -> &[RtoTraceEntry] {
+        &self.trace
+    }
+
+    pub fn sample(&mut self, now: Time, seqno: u64, rtt: Time) {
+        let (srtt, rttvar) = match self.srtt {
+            None => (rtt.0, rtt.0 / 2),
+            Some(srtt) => (
+                (7 * srtt.0 + rtt.0) / 8,
+                (3 * self.rttvar.0 + srtt.0.abs_diff(rtt.0)) / 4,
+            ),
+        };
+
+        self.srtt = Some(Time(srtt));
+        self.rttvar = Time(rttvar);
+        self.rto = min(
+            max(Time(srtt + 4 * rttvar), self.min_rto),
+            Time::from_secs(MAX_RTO),
+        );
+        self.log(now, seqno, RtoEvent::Sample(rtt));
+    }
+
+    pub fn discard_sample(&mut self, now: Time, seqno: u64) {
+        self.log(now, seqno, RtoEvent::Ambiguous);
+    }
+
+    /// Doubles the RTO after the expiry of the timer of a packet that departed at `sent`.
+    /// Timers started before the last backoff already expired too early, so they are ignored.
+    pub fn backoff(&mut self, now: Time, seqno: u64, sent: Time) {
+        if self
+            .last_backoff
+            .is_some_and(|last_backoff| sent < last_backoff)
+        {
+            return;
+        }
+
+        self.last_backoff = Some(now);
+        self.rto = min(Time(2 * self.rto.0), Time::from_secs(MAX_RTO));
+        self.log(now, seqno, RtoEvent::Backoff);
+    }
+
+    fn log(&mut self, time: Time, seqno: u64, event: RtoEvent) {
+        self.trace.push(RtoTraceEntry {
+            time,
+            seqno,
+            event,
+            srtt: self.srtt,
+            rttvar: self.rttvar,
+            rto: self.rto,
+        });
+    }
+}